@@ -4,27 +4,44 @@
 extern crate octant_web_sys_client;
 
 use futures::StreamExt;
-use std::rc::Rc;
+use std::{future::pending, rc::Rc, time::Duration};
 use marshal_fixed::encode::full::FixedEncoderBuilder;
 use marshal_json::encode::full::JsonEncoderBuilder;
-use tokio::{sync::mpsc::unbounded_channel, try_join};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    try_join,
+};
 use wasm_bindgen::prelude::*;
 use web_sys::window;
 
-use crate::websocket::WebSocketMessage;
+use crate::{
+    resume::{reload, Backoff, ResumeState},
+    transport::{Frame, TransportKind, TransportRx, TransportTx},
+};
 use octant_error::{octant_error, OctantError, OctantResult};
 use octant_runtime_client::{
-    proto::{DownMessage, DownMessageList, Proto, UpMessageList},
+    error::RpcFailed,
+    proto::{
+        ClientHello, DownMessage, DownMessageList, Proto, SessionHello, UpMessage, UpMessageList,
+        Wire,
+    },
     reexports::marshal::context::OwnedContext,
     runtime::Runtime,
+    version::protocol_version,
+    OctantSerialize,
 };
 
+mod long_poll;
+mod resume;
+mod transport;
 mod websocket;
 
 #[wasm_bindgen(module = "index.js")]
 extern "C" {
     #[wasm_bindgen(js_name = displayError)]
     fn display_error(message: &str);
+    #[wasm_bindgen(js_name = displayReconnecting)]
+    fn display_reconnecting(reconnecting: bool);
 }
 
 #[wasm_bindgen(start)]
@@ -44,14 +61,6 @@ pub async fn main_impl() -> OctantResult<!> {
     let location = window().expect("no window").location();
     let http_proto = location.protocol().map_err(OctantError::from)?;
     let host = location.host().map_err(OctantError::from)?;
-    let path = location.pathname()?;
-    let proto = path
-        .strip_prefix("/site/")
-        .expect("url must start with /site/")
-        .split_once("/")
-        .unwrap()
-        .0
-        .parse::<Proto>()?;
     let ws_proto = match &*http_proto {
         "http:" => "ws:",
         "https:" => "wss:",
@@ -62,49 +71,155 @@ pub async fn main_impl() -> OctantResult<!> {
             ));
         }
     };
-    let url = format!("{ws_proto}//{host}/socket/{proto}");
-    log::info!("Connecting to {:?}", url);
-    let (tx, mut rx) = websocket::connect(&url).await?;
-
+    let socket_url = format!("{ws_proto}//{host}/socket");
+    let poll_url = "/poll/open";
     let (tx_send, mut rx_send) = unbounded_channel();
-    let runtime = Runtime::new(proto, tx_send)?;
+    let mut tx_send = Some(tx_send);
+    // The runtime is created once the server has chosen a wire, and reconnects offer only that wire.
+    let mut session: Option<(Wire, Rc<Runtime>)> = None;
+    let resume = ResumeState::new();
+    let mut backoff = Backoff::new();
+    let mut kind = TransportKind::WebSocket;
+    loop {
+        let offered = match &session {
+            Some((wire, _)) => vec![*wire],
+            None => Wire::all(),
+        };
+        let connection = match kind {
+            TransportKind::WebSocket => {
+                let url = resume.socket_url(&socket_url);
+                log::info!("Connecting to {:?}", url);
+                websocket::connect(&url, &offered).await
+            }
+            TransportKind::LongPoll => {
+                let url = resume.socket_url(&poll_url);
+                log::info!("Long polling {:?}", url);
+                long_poll::connect(&url, &offered).await
+            }
+        };
+        let error = match connection {
+            Ok((wire, tx, rx)) => {
+                let runtime = match &session {
+                    Some((_, runtime)) => runtime.clone(),
+                    None => {
+                        let runtime = Runtime::new(wire.proto, tx_send.take().unwrap())?;
+                        session = Some((wire, runtime.clone()));
+                        runtime
+                    }
+                };
+                match run_socket(wire, &runtime, &resume, &mut backoff, &mut rx_send, tx, rx).await
+                {
+                    Ok(x) => match x {},
+                    Err(e) => e,
+                }
+            }
+            Err(e) if kind == TransportKind::WebSocket && !resume.is_resuming() => {
+                log::warn!("Websocket unavailable, falling back to long polling: {:?}", e);
+                kind = TransportKind::LongPoll;
+                continue;
+            }
+            Err(e) => e,
+        };
+        if !resume.is_resuming() {
+            return Err(error);
+        }
+        log::warn!("Connection lost: {:?}", error);
+        display_reconnecting(true);
+        if !backoff.wait().await? {
+            return Err(error.context("Could not reconnect."));
+        }
+    }
+}
+
+async fn run_socket(
+    wire: Wire,
+    runtime: &Rc<Runtime>,
+    resume: &ResumeState,
+    backoff: &mut Backoff,
+    rx_send: &mut UnboundedReceiver<Box<dyn UpMessage>>,
+    tx: TransportTx,
+    mut rx: TransportRx,
+) -> OctantResult<!> {
+    let proto = wire.proto;
+    tx.send(encode(
+        proto,
+        &ClientHello {
+            protocol: protocol_version(),
+        },
+    )?)?;
+    let hello = rx
+        .next()
+        .await
+        .ok_or_else(|| octant_error!("Connection terminated"))??;
+    let hello = wire.decode::<SessionHello>(&hello, OwnedContext::new().borrow())?;
+    if !resume.on_hello(&*tx, hello.token, hello.received)? {
+        log::warn!("Session cannot be resumed, reloading.");
+        reload()?;
+        return pending().await;
+    }
+    if let Some(idle_timeout) = hello.idle_timeout_millis {
+        tx.set_idle_timeout(Duration::from_millis(idle_timeout));
+    }
+    backoff.reset();
+    display_reconnecting(false);
     let recv_fut = async {
         let mut ctx = OwnedContext::new();
-        ctx.insert_const::<Rc<Runtime>>(&runtime);
+        ctx.insert_const::<Rc<Runtime>>(runtime);
         while let Some(next) = rx.next().await {
             let next = next?;
-            let message = proto.deserialize::<DownMessageList>(next.as_bytes(), ctx.borrow())?;
+            let message = wire.decode::<DownMessageList>(&next, ctx.borrow())?;
             for bytes in message.commands {
-                proto
-                    .deserialize::<Box<dyn DownMessage>>(&bytes, ctx.borrow())?
-                    .run(&runtime)?;
+                let (handle, result) =
+                    match proto.deserialize::<Box<dyn DownMessage>>(&bytes, ctx.borrow()) {
+                        Ok(message) => (message.handle(), message.run(runtime)),
+                        Err(e) => (None, Err(OctantError::from(e))),
+                    };
+                if let Err(error) = result {
+                    log::error!("RPC failed: {:?}", error);
+                    runtime
+                        .sink()
+                        .send(Box::new(RpcFailed { handle, error }));
+                }
+            }
+            resume.on_receive();
+            if resume.needs_ack() {
+                let message = UpMessageList {
+                    ack: resume.ack(),
+                    commands: vec![],
+                };
+                resume.send(&*tx, encode(proto, &message)?)?;
             }
         }
-        Err(octant_error!("Websocket terminated"))
+        Err(octant_error!("Connection terminated"))
     };
     let send_fut = async {
         loop {
             let mut commands = vec![];
             if rx_send.recv_many(&mut commands, usize::MAX).await == 0 {
-                break;
+                return Err(octant_error!("Runtime terminated"));
             }
             let commands = commands
                 .iter()
                 .map(|x| Ok(proto.serialize(x, OwnedContext::new().borrow())?))
                 .collect::<anyhow::Result<Vec<_>>>()?;
-            let message = UpMessageList { commands };
-            let mut ctx = OwnedContext::new();
-            let message = match proto {
-                Proto::Json => WebSocketMessage::Text(
-                    JsonEncoderBuilder::new().serialize(&message, ctx.borrow())?,
-                ),
-                Proto::Fixed => WebSocketMessage::Binary(
-                    FixedEncoderBuilder::new().serialize(&message, ctx.borrow())?,
-                ),
+            let message = UpMessageList {
+                ack: resume.ack(),
+                commands,
             };
-            tx.send(message)?;
+            resume.send(&*tx, encode(proto, &message)?)?;
         }
-        Ok(())
     };
     try_join!(recv_fut, send_fut)?.0
 }
+
+fn encode<T: OctantSerialize>(proto: Proto, message: &T) -> OctantResult<Frame> {
+    let mut ctx = OwnedContext::new();
+    Ok(match proto {
+        Proto::Json => {
+            Frame::Text(JsonEncoderBuilder::new().serialize(message, ctx.borrow())?)
+        }
+        Proto::Fixed => {
+            Frame::Binary(FixedEncoderBuilder::new().serialize(message, ctx.borrow())?)
+        }
+    })
+}
@@ -0,0 +1,144 @@
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{window, Headers, ReadableStreamDefaultReader, RequestInit, Response};
+
+use octant_error::{octant_error, OctantResult};
+use octant_runtime_client::{frame::FrameReader, proto::Wire};
+
+use crate::transport::{Frame, TransportRx, TransportSender, TransportTx};
+
+struct LongPollSender {
+    up: UnboundedSender<Frame>,
+}
+
+impl TransportSender for LongPollSender {
+    fn send(&self, frame: Frame) -> OctantResult<()> {
+        self.up
+            .unbounded_send(frame)
+            .map_err(|_| octant_error!("Long poll connection closed."))
+    }
+}
+
+/// Opens a connection by posting to `open_url`, for when websockets are unavailable. Returns the
+/// wire the server chose from `offered`.
+pub async fn connect(
+    open_url: &str,
+    offered: &[Wire],
+) -> OctantResult<(Wire, TransportTx, TransportRx)> {
+    let headers = Headers::new()?;
+    headers.set(
+        "Octant-Wire",
+        &offered
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    )?;
+    let mut init = RequestInit::new();
+    init.method("POST");
+    init.headers(&headers);
+    let response = send("POST", open_url, &init).await?;
+    let wire = response
+        .headers()
+        .get("Octant-Wire")?
+        .ok_or_else(|| octant_error!("Expected a wire."))?
+        .parse::<Wire>()?;
+    let id = JsFuture::from(response.text()?)
+        .await?
+        .as_string()
+        .ok_or_else(|| octant_error!("Expected a connection id."))?;
+    let url = format!("/poll/{}", id);
+    let (up_tx, up_rx) = mpsc::unbounded();
+    let (down_tx, down_rx) = mpsc::unbounded();
+    spawn_local(send_loop(url.clone(), up_rx, down_tx.clone()));
+    spawn_local(receive_loop(url, down_tx));
+    Ok((
+        wire,
+        Box::new(LongPollSender { up: up_tx }),
+        Box::pin(down_rx),
+    ))
+}
+
+async fn fetch(method: &str, url: &str, body: Option<&[u8]>) -> OctantResult<Response> {
+    let mut init = RequestInit::new();
+    init.method(method);
+    if let Some(body) = body {
+        init.body(Some(&Uint8Array::from(body).into()));
+    }
+    send(method, url, &init).await
+}
+
+async fn send(method: &str, url: &str, init: &RequestInit) -> OctantResult<Response> {
+    let response: Response = JsFuture::from(
+        window()
+            .expect("no window")
+            .fetch_with_str_and_init(url, init),
+    )
+    .await?
+    .dyn_into()
+    .unwrap();
+    if !response.ok() {
+        return Err(octant_error!(
+            "{} {} failed with status {}.",
+            method,
+            url,
+            response.status()
+        ));
+    }
+    Ok(response)
+}
+
+async fn send_loop(
+    url: String,
+    mut up: UnboundedReceiver<Frame>,
+    down: UnboundedSender<OctantResult<Frame>>,
+) {
+    while let Some(frame) = up.next().await {
+        let mut body = vec![];
+        frame.write_to(&mut body);
+        while let Ok(Some(frame)) = up.try_next() {
+            frame.write_to(&mut body);
+        }
+        if let Err(e) = fetch("POST", &url, Some(&body)).await {
+            down.unbounded_send(Err(e)).ok();
+            return;
+        }
+    }
+}
+
+async fn receive_loop(url: String, down: UnboundedSender<OctantResult<Frame>>) {
+    while !down.is_closed() {
+        if let Err(e) = receive(&url, &down).await {
+            down.unbounded_send(Err(e)).ok();
+            return;
+        }
+    }
+}
+
+async fn receive(url: &str, down: &UnboundedSender<OctantResult<Frame>>) -> OctantResult<()> {
+    let response = fetch("GET", url, None).await?;
+    let body = response
+        .body()
+        .ok_or_else(|| octant_error!("Missing response body."))?;
+    let reader: ReadableStreamDefaultReader = body.get_reader().dyn_into().unwrap();
+    let mut frames = FrameReader::new();
+    loop {
+        let chunk = JsFuture::from(reader.read()).await?;
+        if Reflect::get(&chunk, &"done".into())?.is_truthy() {
+            break;
+        }
+        frames.push(&Uint8Array::new(&Reflect::get(&chunk, &"value".into())?).to_vec());
+        while let Some(frame) = frames.next()? {
+            down.unbounded_send(Ok(frame)).ok();
+        }
+    }
+    if !frames.is_empty() {
+        return Err(octant_error!("Truncated frame."));
+    }
+    Ok(())
+}
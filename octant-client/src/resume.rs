@@ -0,0 +1,137 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+};
+
+use js_sys::Promise;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+
+use octant_error::{OctantError, OctantResult};
+
+use crate::transport::{Frame, TransportSender};
+
+const MAX_UNACKED: usize = 256;
+const ACK_INTERVAL: u64 = 32;
+const INITIAL_BACKOFF_MILLIS: i32 = 250;
+const MAX_BACKOFF_MILLIS: i32 = 8000;
+const MAX_ATTEMPTS: usize = 12;
+
+/// Tracks what each side has received, so that a reconnecting client can pick up the session where
+/// the previous socket left off.
+pub struct ResumeState {
+    token: RefCell<Option<String>>,
+    received: Cell<u64>,
+    acked: Cell<u64>,
+    sent: Cell<u64>,
+    unacked: RefCell<VecDeque<Frame>>,
+}
+
+pub struct Backoff {
+    delay: i32,
+    attempts: usize,
+}
+
+impl ResumeState {
+    pub fn new() -> Self {
+        ResumeState {
+            token: RefCell::new(None),
+            received: Cell::new(0),
+            acked: Cell::new(0),
+            sent: Cell::new(0),
+            unacked: RefCell::new(VecDeque::new()),
+        }
+    }
+    pub fn is_resuming(&self) -> bool {
+        self.token.borrow().is_some()
+    }
+    pub fn socket_url(&self, base: &str) -> String {
+        if let Some(token) = &*self.token.borrow() {
+            format!("{}?session={}&received={}", base, token, self.received.get())
+        } else {
+            base.to_owned()
+        }
+    }
+    /// The number of [DownMessageList]s processed so far, to be sent in the next [UpMessageList].
+    ///
+    /// [DownMessageList]: octant_runtime_client::proto::DownMessageList
+    /// [UpMessageList]: octant_runtime_client::proto::UpMessageList
+    pub fn ack(&self) -> u64 {
+        self.acked.set(self.received.get());
+        self.received.get()
+    }
+    pub fn on_receive(&self) {
+        self.received.set(self.received.get() + 1);
+    }
+    pub fn needs_ack(&self) -> bool {
+        self.received.get() - self.acked.get() >= ACK_INTERVAL
+    }
+    /// Records the server's hello. Returns false if the session cannot continue on this socket.
+    pub fn on_hello(
+        &self,
+        tx: &dyn TransportSender,
+        token: String,
+        received: Option<u64>,
+    ) -> OctantResult<bool> {
+        let Some(received) = received else {
+            return Ok(false);
+        };
+        let ref mut unacked = *self.unacked.borrow_mut();
+        let first = self.sent.get() - unacked.len() as u64;
+        if received < first {
+            return Ok(false);
+        }
+        for message in unacked.iter().skip((received - first) as usize) {
+            tx.send(message.clone())?;
+        }
+        *self.token.borrow_mut() = Some(token);
+        Ok(true)
+    }
+    pub fn send(&self, tx: &dyn TransportSender, message: Frame) -> OctantResult<()> {
+        let ref mut unacked = *self.unacked.borrow_mut();
+        unacked.push_back(message.clone());
+        if unacked.len() > MAX_UNACKED {
+            unacked.pop_front();
+        }
+        self.sent.set(self.sent.get() + 1);
+        tx.send(message)
+    }
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Backoff {
+            delay: INITIAL_BACKOFF_MILLIS,
+            attempts: 0,
+        }
+    }
+    pub fn reset(&mut self) {
+        *self = Backoff::new();
+    }
+    /// Waits before the next reconnect attempt. Returns false once the client should give up.
+    pub async fn wait(&mut self) -> OctantResult<bool> {
+        if self.attempts >= MAX_ATTEMPTS {
+            return Ok(false);
+        }
+        sleep(self.delay).await?;
+        self.attempts += 1;
+        self.delay = (self.delay * 2).min(MAX_BACKOFF_MILLIS);
+        Ok(true)
+    }
+}
+
+pub async fn sleep(millis: i32) -> OctantResult<()> {
+    let promise = Promise::new(&mut |resolve, _| {
+        window()
+            .expect("no window")
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+            .unwrap();
+    });
+    JsFuture::from(promise).await.map_err(OctantError::from)?;
+    Ok(())
+}
+
+pub fn reload() -> OctantResult<()> {
+    window().expect("no window").location().reload()?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use std::{pin::Pin, time::Duration};
+
+use futures::Stream;
+
+use octant_error::OctantResult;
+pub use octant_runtime_client::frame::Frame;
+
+/// The sending half of a framed, bidirectional connection to the server.
+pub trait TransportSender {
+    fn send(&self, frame: Frame) -> OctantResult<()>;
+    /// Fails the connection if the server sends nothing for `idle_timeout`.
+    fn set_idle_timeout(&self, _idle_timeout: Duration) {}
+}
+
+pub type TransportTx = Box<dyn TransportSender>;
+pub type TransportRx = Pin<Box<dyn Stream<Item = OctantResult<Frame>>>>;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TransportKind {
+    WebSocket,
+    LongPoll,
+}
@@ -1,15 +1,27 @@
 use core::mem;
-use std::{pin::Pin, rc::Rc, str, task::Poll};
+use std::{cell::Cell, pin::Pin, rc::Rc, str, task::Poll, time::Duration};
 
 use futures::Stream;
 use tokio::sync::mpsc;
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
-use web_sys::{CloseEvent, ErrorEvent, Event, MessageEvent, WebSocket};
+use web_sys::{window, CloseEvent, ErrorEvent, Event, MessageEvent, WebSocket};
 
 use octant_error::{octant_error, Context, OctantError, OctantResult};
+use octant_runtime_client::proto::Wire;
+
+use crate::transport::{Frame, TransportRx, TransportSender, TransportTx};
 
 struct WebSocketStream {
     socket: WebSocket,
+    watchdog: Rc<IdleWatchdog>,
+}
+
+/// Fails the connection when the server has been silent for too long. A healthy server sends
+/// heartbeats, so silence means the connection is half-open.
+struct IdleWatchdog {
+    timeout: Cell<Option<Duration>>,
+    timer: Cell<Option<i32>>,
+    on_idle: Closure<dyn FnMut()>,
 }
 
 pub struct WebSocketSender {
@@ -21,18 +33,43 @@ pub struct WebSocketReceiver {
     receiver: mpsc::UnboundedReceiver<WebSocketEvent>,
 }
 
-pub enum WebSocketMessage {
-    Text(String),
-    Binary(Vec<u8>),
-}
-
 enum WebSocketEvent {
     Connect,
     Error(ErrorEvent),
-    Message(WebSocketMessage),
+    Message(Frame),
+    Idle,
     Close,
 }
 
+impl IdleWatchdog {
+    /// Restarts the timer, after a frame was received or the timeout changed.
+    fn reset(&self) {
+        let window = window().expect("no window");
+        if let Some(timer) = self.timer.take() {
+            window.clear_timeout_with_handle(timer);
+        }
+        if let Some(timeout) = self.timeout.get() {
+            match window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                self.on_idle.as_ref().unchecked_ref(),
+                timeout.as_millis() as i32,
+            ) {
+                Ok(timer) => self.timer.set(Some(timer)),
+                Err(e) => log::error!("Cannot start idle timer: {:?}", e),
+            }
+        }
+    }
+}
+
+impl Drop for IdleWatchdog {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            window()
+                .expect("no window")
+                .clear_timeout_with_handle(timer);
+        }
+    }
+}
+
 impl Drop for WebSocketStream {
     fn drop(&mut self) {
         if let Err(e) = self.socket.close() {
@@ -40,28 +77,19 @@ impl Drop for WebSocketStream {
         }
     }
 }
-//
-// impl WebSocketMessage {
-//     pub fn as_str(&self) -> Result<&str, Utf8Error> {
-//         match self {
-//             WebSocketMessage::Text(x) => Ok(x),
-//             WebSocketMessage::Binary(x) => str::from_utf8(x),
-//         }
-//     }
-// }
 
 impl WebSocketStream {
-    pub fn send(&self, message: WebSocketMessage) -> OctantResult<()> {
-        match message {
-            WebSocketMessage::Text(x) => self.socket.send_with_str(&x),
-            WebSocketMessage::Binary(x) => self.socket.send_with_u8_array(&x),
+    pub fn send(&self, frame: Frame) -> OctantResult<()> {
+        match frame {
+            Frame::Text(x) => self.socket.send_with_str(&x),
+            Frame::Binary(x) => self.socket.send_with_u8_array(&x),
         }
         .context("Failed to send.")
     }
 }
 
 impl Stream for WebSocketReceiver {
-    type Item = OctantResult<WebSocketMessage>;
+    type Item = OctantResult<Frame>;
 
     fn poll_next(
         mut self: Pin<&mut Self>,
@@ -70,6 +98,9 @@ impl Stream for WebSocketReceiver {
         match self.receiver.poll_recv(cx) {
             Poll::Ready(Some(WebSocketEvent::Connect)) => unreachable!(),
             Poll::Ready(Some(WebSocketEvent::Message(x))) => Poll::Ready(Some(Ok(x))),
+            Poll::Ready(Some(WebSocketEvent::Idle)) => {
+                Poll::Ready(Some(Err(octant_error!("Server stopped responding."))))
+            }
             Poll::Ready(Some(WebSocketEvent::Error(e))) => {
                 Poll::Ready(Some(Err(OctantError::from(JsValue::from(e)))))
             }
@@ -80,17 +111,42 @@ impl Stream for WebSocketReceiver {
     }
 }
 
-impl WebSocketSender {
-    pub fn send(&self, message: WebSocketMessage) -> OctantResult<()> {
-        self.stream.send(message)
+impl TransportSender for WebSocketSender {
+    fn send(&self, frame: Frame) -> OctantResult<()> {
+        self.stream.send(frame)
+    }
+    fn set_idle_timeout(&self, idle_timeout: Duration) {
+        self.stream.watchdog.timeout.set(Some(idle_timeout));
+        self.stream.watchdog.reset();
     }
 }
 
-pub async fn connect(address: &str) -> OctantResult<(WebSocketSender, WebSocketReceiver)> {
-    let socket = WebSocket::new(address).context("Failed to create socket.")?;
+/// Connects to `address`, offering each of `offered` as a subprotocol. Returns the wire the server
+/// chose.
+pub async fn connect(
+    address: &str,
+    offered: &[Wire],
+) -> OctantResult<(Wire, TransportTx, TransportRx)> {
+    let protocols = offered
+        .iter()
+        .map(|x| JsValue::from(x.to_string()))
+        .collect::<js_sys::Array>();
+    let socket = WebSocket::new_with_str_sequence(address, &protocols)
+        .context("Failed to create socket.")?;
     let (recv_tx, mut recv_rx) = mpsc::unbounded_channel();
     socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
 
+    let watchdog = Rc::new(IdleWatchdog {
+        timeout: Cell::new(None),
+        timer: Cell::new(None),
+        on_idle: Closure::new({
+            let recv_tx = recv_tx.clone();
+            move || {
+                recv_tx.send(WebSocketEvent::Idle).ok();
+            }
+        }),
+    });
+
     let onerror_callback: Closure<dyn FnMut(ErrorEvent)> = Closure::new({
         let recv_tx = recv_tx.clone();
         move |e: ErrorEvent| {
@@ -109,20 +165,22 @@ pub async fn connect(address: &str) -> OctantResult<(WebSocketSender, WebSocketR
 
     let onmessage_callback: Closure<dyn FnMut(MessageEvent)> = Closure::new({
         let recv_tx = recv_tx.clone();
+        let watchdog = watchdog.clone();
         move |e: MessageEvent| {
+            watchdog.reset();
             if let Ok(abuf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
                 let array = js_sys::Uint8Array::new(&abuf);
                 let mut vec = vec![];
                 vec.resize(array.length() as usize, 0);
                 array.copy_to(&mut vec);
                 recv_tx
-                    .send(WebSocketEvent::Message(WebSocketMessage::Binary(vec)))
+                    .send(WebSocketEvent::Message(Frame::Binary(vec)))
                     .ok();
             } else if let Ok(_) = e.data().dyn_into::<web_sys::Blob>() {
                 unreachable!();
             } else if let Ok(abuf) = e.data().dyn_into::<js_sys::JsString>() {
                 recv_tx
-                    .send(WebSocketEvent::Message(WebSocketMessage::Text(
+                    .send(WebSocketEvent::Message(Frame::Text(
                         String::from(abuf),
                     )))
                     .ok();
@@ -147,29 +205,22 @@ pub async fn connect(address: &str) -> OctantResult<(WebSocketSender, WebSocketR
             log::error!("receive error");
             return Err(OctantError::from(JsValue::from(e)).context("Failed to connect."));
         }
-        WebSocketEvent::Message(_) => unreachable!(),
+        WebSocketEvent::Message(_) | WebSocketEvent::Idle => unreachable!(),
         WebSocketEvent::Close => {
             return Err(octant_error!("Connection closed."));
         }
     }
 
-    let stream = Rc::new(WebSocketStream { socket });
+    let wire = socket.protocol().parse::<Wire>()?;
+    let stream = Rc::new(WebSocketStream { socket, watchdog });
     Ok((
-        WebSocketSender {
+        wire,
+        Box::new(WebSocketSender {
             stream: stream.clone(),
-        },
-        WebSocketReceiver {
+        }),
+        Box::pin(WebSocketReceiver {
             _stream: stream.clone(),
             receiver: recv_rx,
-        },
+        }),
     ))
 }
-
-impl WebSocketMessage {
-    pub fn as_bytes(&self) -> &[u8] {
-        match self {
-            WebSocketMessage::Text(x) => x.as_bytes(),
-            WebSocketMessage::Binary(x) => x,
-        }
-    }
-}
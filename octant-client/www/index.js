@@ -2,4 +2,7 @@ export function displayError(message) {
     console.log("error = ", message);
     document.getElementById("message").appendChild(document.createTextNode(message))
     document.getElementById("notification").style.display = "block"
-}
\ No newline at end of file
+}
+export function displayReconnecting(reconnecting) {
+    document.getElementById("reconnecting").style.display = reconnecting ? "block" : "none"
+}
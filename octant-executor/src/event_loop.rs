@@ -1,6 +1,8 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     future::{Future, poll_fn},
+    panic::AssertUnwindSafe,
+    pin::Pin,
     rc,
     rc::Rc,
     sync::{
@@ -10,11 +12,12 @@ use std::{
     task::{Context, Poll, ready, Wake, Waker},
 };
 
-use futures::future::LocalBoxFuture;
+use futures::{future::LocalBoxFuture, task::noop_waker_ref};
 use slab::Slab;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
-use octant_error::OctantResult;
+use octant_error::{octant_error, OctantError, OctantResult};
+use octant_panic::catch_error;
 
 #[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Hash, Debug)]
 struct EventTaskId(usize);
@@ -25,14 +28,28 @@ struct EventWaker {
     queue: Arc<EventQueue>,
 }
 
+/// What an [EventPool] does when one of its tasks returns an error or panics.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Supervision {
+    /// Log the error and keep running the other tasks.
+    Log,
+    /// Pass the error to the pool's error hook and keep running the other tasks.
+    Report,
+    /// Stop the pool, returning the error from [EventPool::run].
+    #[default]
+    Abort,
+}
+
 struct EventTask {
     waker: Arc<EventWaker>,
     spawn: Rc<EventSpawn>,
+    supervision: Supervision,
     inner: RefCell<LocalBoxFuture<'static, OctantResult<()>>>,
 }
 
 struct TaskSet {
     tasks: RefCell<Slab<Rc<EventTask>>>,
+    supervision: Cell<Supervision>,
 }
 
 struct EventQueue {
@@ -45,16 +62,66 @@ pub struct EventSpawn {
     task_set: rc::Weak<TaskSet>,
 }
 
+/// A task spawned on an [EventPool]. Awaiting the handle yields the task's result, or an error if
+/// the task failed, panicked, or was aborted. Dropping the handle detaches the task.
+pub struct JoinHandle<T> {
+    task: rc::Weak<EventTask>,
+    task_set: rc::Weak<TaskSet>,
+    finished: Rc<Cell<bool>>,
+    result: oneshot::Receiver<OctantResult<T>>,
+}
+
+/// A [JoinHandle] that aborts its task when dropped.
+pub struct AbortOnDrop<T>(JoinHandle<T>);
+
 pub struct EventPool {
     microtasks: mpsc::UnboundedReceiver<EventTaskId>,
     macrotasks: mpsc::UnboundedReceiver<EventTaskId>,
     task_set: Rc<TaskSet>,
     flushing: bool,
+    flush_first: bool,
     poll_flush: Box<dyn FnMut(&mut Context<'_>) -> Poll<OctantResult<()>>>,
+    error_hook: Option<Box<dyn FnMut(OctantError)>>,
+    order: Option<SeededOrder>,
+    ready: Vec<EventTaskId>,
+}
+
+/// What [EventPool::step] did.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Step {
+    Microtask,
+    Macrotask,
+    Flush,
 }
 
+/// A splitmix64 generator, for picking among ready work reproducibly.
+pub(crate) struct SeededOrder(pub(crate) u64);
+
 impl EventTask {}
 
+impl SeededOrder {
+    pub(crate) fn next(&mut self, bound: usize) -> usize {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z % bound as u64) as usize
+    }
+}
+
+impl TaskSet {
+    fn remove(&self, task: &Rc<EventTask>) -> Option<Rc<EventTask>> {
+        let ref mut tasks = *self.tasks.borrow_mut();
+        let id = task.waker.id.0;
+        if tasks.get(id).is_some_and(|x| Rc::ptr_eq(x, task)) {
+            Some(tasks.remove(id))
+        } else {
+            None
+        }
+    }
+}
+
 impl Wake for EventWaker {
     fn wake(self: Arc<Self>) {
         if !self.woken.swap(true, Ordering::SeqCst) {
@@ -71,6 +138,7 @@ impl EventPool {
         let (macro_tx, macro_rx) = mpsc::unbounded_channel();
         let task_set = Rc::new(TaskSet {
             tasks: RefCell::new(Slab::new()),
+            supervision: Cell::new(Supervision::default()),
         });
         let spawn = Rc::new(EventSpawn {
             queue: Arc::new(EventQueue {
@@ -84,13 +152,40 @@ impl EventPool {
             macrotasks: macro_rx,
             task_set,
             flushing: false,
+            flush_first: false,
             poll_flush: Box::new(poll_flush),
+            error_hook: None,
+            order: None,
+            ready: vec![],
         };
         (spawn, pool)
     }
 
+    /// Sets the [Supervision] of tasks spawned without one. Defaults to [Supervision::Abort].
+    pub fn with_supervision(self, supervision: Supervision) -> Self {
+        self.task_set.supervision.set(supervision);
+        self
+    }
+
+    /// Runs ready macrotasks in an order chosen by `seed` instead of the order they were woken in,
+    /// to reproduce races between events. Microtasks still run in order.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.order = Some(SeededOrder(seed));
+        self
+    }
+
+    /// Sets the hook that receives the errors of tasks supervised with [Supervision::Report].
+    /// Without one, those errors are logged.
+    pub fn with_error_hook(mut self, hook: impl 'static + FnMut(OctantError)) -> Self {
+        self.error_hook = Some(Box::new(hook));
+        self
+    }
+
     fn poll_once(&mut self, id: EventTaskId) -> OctantResult<()> {
-        let task = self.task_set.tasks.borrow_mut().get(id.0).unwrap().clone();
+        let Some(task) = self.task_set.tasks.borrow().get(id.0).cloned() else {
+            // The task was aborted after it was woken.
+            return Ok(());
+        };
         task.waker.woken.store(false, Ordering::SeqCst);
         let polled = task
             .inner
@@ -98,108 +193,303 @@ impl EventPool {
             .as_mut()
             .poll(&mut Context::from_waker(&Waker::from(task.waker.clone())));
         match polled {
-            Poll::Ready(r) => {
-                self.task_set.tasks.borrow_mut().remove(id.0);
-                r?;
-            }
             Poll::Pending => {}
+            Poll::Ready(Ok(())) => {
+                self.task_set.remove(&task);
+            }
+            Poll::Ready(Err(e)) => {
+                self.task_set.remove(&task);
+                self.supervise(task.supervision, e)?;
+            }
         }
         Ok(())
     }
 
-    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
-        'system: loop {
-            if self.flushing {
-                ready!((self.poll_flush)(cx))?;
-                self.flushing = false;
+    fn supervise(&mut self, supervision: Supervision, e: OctantError) -> OctantResult<()> {
+        match (supervision, &mut self.error_hook) {
+            (Supervision::Abort, _) => return Err(e),
+            (Supervision::Report, Some(hook)) => hook(e),
+            (Supervision::Log | Supervision::Report, _) => {
+                tracing::error!("Task failed: {:?}", e)
             }
-            'user: loop {
-                let mut pending = false;
-                pending |= match self.microtasks.poll_recv(cx) {
-                    Poll::Ready(Some(task)) => {
-                        self.poll_once(task)?;
-                        self.flushing = true;
-                        continue 'user;
-                    }
-                    Poll::Ready(None) => false,
-                    Poll::Pending => true,
-                };
-                pending |= match self.macrotasks.poll_recv(cx) {
-                    Poll::Ready(Some(task)) => {
-                        self.poll_once(task)?;
-                        self.flushing = true;
-                        continue 'system;
-                    }
-                    Poll::Ready(None) => false,
-                    Poll::Pending => true,
-                };
-                if self.flushing {
-                    ready!((self.poll_flush)(cx))?;
-                    self.flushing = false;
+        }
+        Ok(())
+    }
+
+    /// Takes the next macrotask, choosing among all that are ready if the pool is seeded.
+    fn poll_macrotask(&mut self, cx: &mut Context<'_>) -> Poll<Option<EventTaskId>> {
+        let Some(order) = &mut self.order else {
+            return self.macrotasks.poll_recv(cx);
+        };
+        let mut closed = false;
+        loop {
+            match self.macrotasks.poll_recv(cx) {
+                Poll::Ready(Some(task)) => self.ready.push(task),
+                Poll::Ready(None) => {
+                    closed = true;
+                    break;
                 }
-                return if pending {
-                    Poll::Pending
-                } else {
-                    Poll::Ready(Ok(()))
-                };
+                Poll::Pending => break,
+            }
+        }
+        if !self.ready.is_empty() {
+            let index = order.next(self.ready.len());
+            Poll::Ready(Some(self.ready.swap_remove(index)))
+        } else if closed {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Runs one task or flush. Microtasks run first, and the messages they send are flushed
+    /// after the next macrotask, or once there are no tasks left to run. The messages sent by a
+    /// macrotask are flushed before anything else runs.
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<Option<Step>>> {
+        if self.flush_first {
+            ready!((self.poll_flush)(cx))?;
+            self.flushing = false;
+            self.flush_first = false;
+            return Poll::Ready(Ok(Some(Step::Flush)));
+        }
+        let mut pending = false;
+        match self.microtasks.poll_recv(cx) {
+            Poll::Ready(Some(task)) => {
+                self.poll_once(task)?;
+                self.flushing = true;
+                return Poll::Ready(Ok(Some(Step::Microtask)));
             }
+            Poll::Ready(None) => {}
+            Poll::Pending => pending = true,
         }
+        match self.poll_macrotask(cx) {
+            Poll::Ready(Some(task)) => {
+                self.poll_once(task)?;
+                self.flushing = true;
+                self.flush_first = true;
+                return Poll::Ready(Ok(Some(Step::Macrotask)));
+            }
+            Poll::Ready(None) => {}
+            Poll::Pending => pending = true,
+        }
+        if self.flushing {
+            self.flush_first = true;
+            return self.poll_next(cx);
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(None))
+        }
+    }
+
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        while ready!(self.poll_next(cx))?.is_some() {}
+        Poll::Ready(Ok(()))
     }
     pub async fn run(&mut self) -> OctantResult<()> {
         poll_fn(|cx| self.poll_step(cx)).await
     }
+
+    /// Runs a single task or flush without waiting, for tests that interleave events with the
+    /// pool's work. Returns `None` if nothing is ready, or if a flush is not ready to complete.
+    ///
+    /// Combined with a paused tokio clock and [Self::with_seed], this runs the pool
+    /// deterministically. Tasks should yield with [yield_now] rather than tokio's, which defers
+    /// waking them until the caller yields to tokio.
+    pub fn step(&mut self) -> OctantResult<Option<Step>> {
+        match self.poll_next(&mut Context::from_waker(noop_waker_ref())) {
+            Poll::Ready(step) => step,
+            Poll::Pending => Ok(None),
+        }
+    }
+
+    /// Runs tasks and flushes until nothing is ready, returning what ran.
+    pub fn run_until_stalled(&mut self) -> OctantResult<Vec<Step>> {
+        let mut steps = vec![];
+        while let Some(step) = self.step()? {
+            steps.push(step);
+        }
+        Ok(steps)
+    }
 }
 
 impl EventSpawn {
-    fn try_insert<F: 'static + Future<Output = OctantResult<()>>>(
+    fn insert<T: 'static, F: 'static + Future<Output = OctantResult<T>>>(
         self: &Rc<EventSpawn>,
+        supervision: Option<Supervision>,
+        queue: &mpsc::UnboundedSender<EventTaskId>,
         f: F,
-    ) -> Option<EventTaskId> {
-        if let Some(tasks) = self.task_set.upgrade() {
-            let ref mut tasks = *tasks.tasks.borrow_mut();
+    ) -> JoinHandle<T> {
+        let (tx, rx) = oneshot::channel();
+        let mut tx = Some(tx);
+        let finished = Rc::new(Cell::new(false));
+        let mut f = Box::pin(f);
+        let mut task = rc::Weak::new();
+        if let Some(task_set) = self.task_set.upgrade() {
+            let supervision = supervision.unwrap_or(task_set.supervision.get());
+            let ref mut tasks = *task_set.tasks.borrow_mut();
             let id = EventTaskId(tasks.vacant_key());
             let waker = Arc::new(EventWaker {
                 woken: AtomicBool::new(true),
                 queue: self.queue.clone(),
                 id,
             });
-            tasks.insert(Rc::new(EventTask {
-                inner: RefCell::new(Box::pin(f)),
+            let finished = finished.clone();
+            let inserted = Rc::new(EventTask {
+                inner: RefCell::new(Box::pin(poll_fn(move |cx| {
+                    // Panics are caught here rather than by the pool, so that the join handle
+                    // receives them too.
+                    let result = match catch_error(AssertUnwindSafe(|| f.as_mut().poll(cx))) {
+                        Ok(Poll::Pending) => return Poll::Pending,
+                        Ok(Poll::Ready(result)) => result,
+                        Err(e) => Err(e),
+                    };
+                    let tx = tx.take().expect("task polled after completion");
+                    finished.set(true);
+                    Poll::Ready(match result {
+                        Ok(x) => {
+                            tx.send(Ok(x)).ok();
+                            Ok(())
+                        }
+                        Err(e) => {
+                            tx.send(Err(OctantError::from(&e))).ok();
+                            Err(e)
+                        }
+                    })
+                }))),
                 waker: waker.clone(),
                 spawn: self.clone(),
-            }));
-            Some(id)
-        } else {
-            None
+                supervision,
+            });
+            task = Rc::downgrade(&inserted);
+            tasks.insert(inserted);
+            queue.send(id).ok();
         }
-    }
-    pub fn spawn<F: 'static + Future<Output = OctantResult<()>>>(self: &Rc<Self>, f: F) {
-        if let Some(id) = self.try_insert(f) {
-            self.queue.microtasks.send(id).ok();
+        JoinHandle {
+            task,
+            task_set: self.task_set.clone(),
+            finished,
+            result: rx,
         }
     }
-    pub fn spawn_macro<F: 'static + Future<Output = OctantResult<()>>>(self: &Rc<Self>, f: F) {
-        if let Some(id) = self.try_insert(f) {
-            self.queue.macrotasks.send(id).ok();
+    pub fn spawn<T: 'static, F: 'static + Future<Output = OctantResult<T>>>(
+        self: &Rc<Self>,
+        f: F,
+    ) -> JoinHandle<T> {
+        self.insert(None, &self.queue.microtasks, f)
+    }
+    /// Like [Self::spawn], but overrides the pool's [Supervision] for this task.
+    pub fn spawn_supervised<T: 'static, F: 'static + Future<Output = OctantResult<T>>>(
+        self: &Rc<Self>,
+        supervision: Supervision,
+        f: F,
+    ) -> JoinHandle<T> {
+        self.insert(Some(supervision), &self.queue.microtasks, f)
+    }
+    pub fn spawn_macro<T: 'static, F: 'static + Future<Output = OctantResult<T>>>(
+        self: &Rc<Self>,
+        f: F,
+    ) -> JoinHandle<T> {
+        self.insert(None, &self.queue.macrotasks, f)
+    }
+    /// The number of spawned tasks that have not yet finished.
+    pub fn task_count(&self) -> usize {
+        self.task_set
+            .upgrade()
+            .map_or(0, |tasks| tasks.tasks.borrow().len())
+    }
+}
+
+/// Lets the pool run other macrotasks, and flush, before continuing. Unlike tokio's `yield_now`,
+/// this wakes the task immediately, so it is also suitable for [EventPool::step].
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
         }
+    })
+    .await
+}
+
+impl<T> JoinHandle<T> {
+    /// Drops the task without polling it again. Does nothing if the task has finished.
+    pub fn abort(&self) {
+        if let (Some(task), Some(task_set)) = (self.task.upgrade(), self.task_set.upgrade()) {
+            if task_set.remove(&task).is_some() {
+                self.finished.set(true);
+            }
+        }
+    }
+    /// Whether the task has returned, failed, panicked, or been aborted through this handle. A task
+    /// that was dropped with its pool never finishes.
+    pub fn is_finished(&self) -> bool {
+        self.finished.get()
+    }
+    /// Ties the task to the lifetime of the returned guard.
+    pub fn abort_on_drop(self) -> AbortOnDrop<T> {
+        AbortOnDrop(self)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = OctantResult<T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.result)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(octant_error!("Task was cancelled"))))
+    }
+}
+
+impl<T> AbortOnDrop<T> {
+    pub fn abort(&self) {
+        self.0.abort()
+    }
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = OctantResult<T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort()
     }
 }
 
 #[cfg(test)]
 mod test {
     use std::{
+        cell::RefCell,
+        collections::HashSet,
         future::{Future, pending, poll_fn},
         mem,
         pin::pin,
+        rc::Rc,
         task::Poll,
+        time::Duration,
     };
 
     use parking_lot::Mutex;
     use tokio::task::yield_now;
 
-    use octant_error::OctantResult;
+    use octant_error::{octant_error, OctantResult};
 
-    use crate::event_loop::EventPool;
+    use crate::{
+        event_loop::{self, EventPool, Step, Supervision},
+        timer::sleep,
+    };
 
     #[tokio::test]
     async fn test() -> OctantResult<()> {
@@ -289,4 +579,239 @@ mod test {
 
         Ok(())
     }
+    #[tokio::test]
+    async fn test_task_count() -> OctantResult<()> {
+        static COUNTS: Mutex<Vec<usize>> = Mutex::new(vec![]);
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        spawn.spawn(async move {
+            yield_now().await;
+            Ok(())
+        });
+        spawn.spawn({
+            let spawn = spawn.clone();
+            async move {
+                COUNTS.lock().push(spawn.task_count());
+                // Tokio may wake yielding tasks in either order, so yield until the other task
+                // has certainly finished.
+                yield_now().await;
+                yield_now().await;
+                COUNTS.lock().push(spawn.task_count());
+                Ok(())
+            }
+        });
+        assert_eq!(spawn.task_count(), 2);
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(*COUNTS.lock(), vec![2, 1]);
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_supervision() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let (spawn, pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let mut pool = pool
+            .with_supervision(Supervision::Report)
+            .with_error_hook(|e| LOG.lock().push(format!("{}", e)));
+        spawn.spawn(async move { Err::<(), _>(octant_error!("failed")) });
+        let panicked = spawn.spawn::<!, _>(async move {
+            yield_now().await;
+            panic!("panicked");
+        });
+        spawn.spawn_supervised(Supervision::Log, async move {
+            Err::<(), _>(octant_error!("logged"))
+        });
+        spawn.spawn(async move {
+            let e = panicked.await.unwrap_err();
+            LOG.lock().push(format!("joined {}", e));
+            Ok(())
+        });
+        spawn.spawn(async move {
+            yield_now().await;
+            yield_now().await;
+            LOG.lock().push(format!("finished"));
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        let log = LOG.lock();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[0], "failed");
+        assert!(log[1].starts_with("panicked"));
+        assert!(log[2].starts_with("joined panicked"));
+        assert_eq!(log[3], "finished");
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_abort() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let (spawn, pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let mut pool = pool.with_supervision(Supervision::Log);
+        spawn.spawn_supervised(Supervision::Abort, async move {
+            yield_now().await;
+            Err::<(), _>(octant_error!("aborted"))
+        });
+        spawn.spawn(async move {
+            yield_now().await;
+            yield_now().await;
+            LOG.lock().push(format!("finished"));
+            Ok(())
+        });
+        mem::drop(spawn);
+        assert_eq!(format!("{}", pool.run().await.unwrap_err()), "aborted");
+        assert_eq!(LOG.lock().iter().collect::<Vec<_>>(), Vec::<&str>::new());
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_join_handle() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let answer = spawn.spawn(async move {
+            yield_now().await;
+            Ok(42)
+        });
+        let aborted = spawn.spawn(async move {
+            yield_now().await;
+            LOG.lock().push(format!("not aborted"));
+            Ok(())
+        });
+        spawn.spawn(async move {
+            aborted.abort();
+            let answer = answer.await?;
+            LOG.lock().push(format!("answer {}", answer));
+            let aborted = aborted.await.unwrap_err();
+            LOG.lock().push(format!("{}", aborted));
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec!["answer 42", "Task was cancelled"]
+        );
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_abort_on_drop() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let guard = spawn
+            .spawn(async move {
+                struct Foo;
+                impl Drop for Foo {
+                    fn drop(&mut self) {
+                        LOG.lock().push(format!("dropped"));
+                    }
+                }
+                let _foo = Foo;
+                pending::<()>().await;
+                Ok(())
+            })
+            .abort_on_drop();
+        spawn.spawn(async move {
+            yield_now().await;
+            assert!(!guard.is_finished());
+            mem::drop(guard);
+            LOG.lock().push(format!("aborted"));
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec!["dropped", "aborted"]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_is_finished() -> OctantResult<()> {
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let returned = spawn.spawn(async move { Ok(()) });
+        let aborted = spawn.spawn(pending::<OctantResult<()>>());
+        let dropped = spawn.spawn(pending::<OctantResult<()>>());
+        assert!(!returned.is_finished());
+        pool.run_until_stalled()?;
+        assert!(returned.is_finished());
+        assert!(!aborted.is_finished());
+        aborted.abort();
+        assert!(aborted.is_finished());
+        mem::drop(pool);
+        assert!(!dropped.is_finished());
+        let never_spawned = spawn.spawn(async move { Ok(()) });
+        assert!(!never_spawned.is_finished());
+        Ok(())
+    }
+    #[test]
+    fn test_step() -> OctantResult<()> {
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        spawn.spawn({
+            let spawn = spawn.clone();
+            async move {
+                spawn.spawn(async move { Ok(()) });
+                event_loop::yield_now().await;
+                Ok(())
+            }
+        });
+        assert_eq!(
+            pool.run_until_stalled()?,
+            vec![
+                Step::Microtask,
+                Step::Microtask,
+                Step::Macrotask,
+                Step::Flush
+            ]
+        );
+        spawn.spawn_macro(async move { Ok(()) });
+        assert_eq!(pool.step()?, Some(Step::Macrotask));
+        assert_eq!(pool.step()?, Some(Step::Flush));
+        assert_eq!(pool.step()?, None);
+        Ok(())
+    }
+    fn seeded_order(seed: u64) -> OctantResult<Vec<usize>> {
+        let log = Rc::new(RefCell::new(vec![]));
+        let (spawn, pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let mut pool = pool.with_seed(seed);
+        for index in 0..8 {
+            let log = log.clone();
+            spawn.spawn_macro(async move {
+                log.borrow_mut().push(index);
+                Ok(())
+            });
+        }
+        pool.run_until_stalled()?;
+        Ok(log.take())
+    }
+    #[test]
+    fn test_seed() -> OctantResult<()> {
+        let order = seeded_order(1)?;
+        assert_eq!(order, seeded_order(1)?);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+        let orders = (0..8)
+            .map(seeded_order)
+            .collect::<OctantResult<HashSet<_>>>()?;
+        assert!(orders.len() > 1);
+        Ok(())
+    }
+    #[tokio::test(start_paused = true)]
+    async fn test_virtual_time() -> OctantResult<()> {
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let timer = spawn.spawn(async move {
+            sleep(Duration::from_secs(60)).await;
+            Ok(())
+        });
+        assert_eq!(
+            pool.run_until_stalled()?,
+            vec![Step::Microtask, Step::Flush]
+        );
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert_eq!(pool.run_until_stalled()?, vec![]);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(
+            pool.run_until_stalled()?,
+            vec![Step::Macrotask, Step::Flush]
+        );
+        assert!(timer.is_finished());
+        Ok(())
+    }
 }
@@ -5,4 +5,5 @@
 
 pub mod local_set;
 pub mod event_loop;
+pub mod timer;
 
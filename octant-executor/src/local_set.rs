@@ -1,39 +1,85 @@
 use std::{
-    future::Future,
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    mem,
+    pin::pin,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
     },
+    task::{Context, Poll},
 };
 
+use futures::task::noop_waker_ref;
 use tokio::{
     runtime,
     sync::{
-        mpsc::{unbounded_channel, UnboundedSender},
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
     task,
     task::{JoinError, JoinSet, LocalSet},
 };
 
+use crate::event_loop::SeededOrder;
+
 type Task = Box<dyn 'static + Sync + Send + FnOnce()>;
 
 pub struct LocalSetSpawn {
-    channels: Vec<UnboundedSender<Task>>,
+    threads: Vec<Worker>,
     next: AtomicUsize,
 }
 
+struct Worker {
+    tx: UnboundedSender<Task>,
+    load: Arc<ThreadLoad>,
+}
+
+#[derive(Default)]
+struct ThreadLoad {
+    active: AtomicUsize,
+    queued: AtomicUsize,
+    spawned: AtomicU64,
+}
+
+/// Counts a future spawned by [LocalSetSpawn] as active on its thread until it completes.
+struct ActiveGuard(Arc<ThreadLoad>);
+
+/// The load on one thread of a [LocalSetPool].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct ThreadStats {
+    /// Futures running on the thread, such as sessions.
+    pub active_tasks: usize,
+    /// Work sent to the thread that has not started yet.
+    pub queued_tasks: usize,
+    /// All work ever sent to the thread.
+    pub spawned_tasks: u64,
+}
+
 pub struct LocalSetPool {
     joins: JoinSet<()>,
 }
 
+/// A stand-in for a [LocalSetPool] in tests, which runs the work of its simulated threads on the
+/// calling thread, and only within [Self::step] or [Self::run_until]. The seed picks which thread
+/// runs next, so a failing interleaving can be replayed. On a paused tokio clock, timers use
+/// virtual time.
+pub struct DeterministicPool {
+    threads: Vec<UnboundedReceiver<Task>>,
+    queued: Vec<VecDeque<Task>>,
+    order: SeededOrder,
+}
+
 impl LocalSetPool {
     pub fn new(threads: usize) -> (Arc<LocalSetSpawn>, LocalSetPool) {
-        let mut channels = Vec::with_capacity(threads);
+        let mut workers = Vec::with_capacity(threads);
         let mut joins = JoinSet::new();
         for _ in 0..threads {
             let (tx, mut rx) = unbounded_channel::<Task>();
-            channels.push(tx);
+            workers.push(Worker {
+                tx,
+                load: Arc::new(ThreadLoad::default()),
+            });
             joins.spawn_blocking(move || {
                 let locals = LocalSet::new();
                 runtime::Handle::current().block_on(locals.run_until(async move {
@@ -45,7 +91,7 @@ impl LocalSetPool {
         }
         (
             Arc::new(LocalSetSpawn {
-                channels,
+                threads: workers,
                 next: AtomicUsize::new(0),
             }),
             LocalSetPool { joins },
@@ -62,14 +108,128 @@ impl LocalSetPool {
     }
 }
 
+impl DeterministicPool {
+    pub fn new(threads: usize, seed: u64) -> (Arc<LocalSetSpawn>, DeterministicPool) {
+        let mut workers = Vec::with_capacity(threads);
+        let mut receivers = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let (tx, rx) = unbounded_channel::<Task>();
+            workers.push(Worker {
+                tx,
+                load: Arc::new(ThreadLoad::default()),
+            });
+            receivers.push(rx);
+        }
+        (
+            Arc::new(LocalSetSpawn {
+                threads: workers,
+                next: AtomicUsize::new(0),
+            }),
+            DeterministicPool {
+                queued: receivers.iter().map(|_| VecDeque::new()).collect(),
+                threads: receivers,
+                order: SeededOrder(seed),
+            },
+        )
+    }
+    /// Starts the next piece of work on a thread chosen by the seed, returning false if none is
+    /// queued. Must be called within a [LocalSet], which runs the spawned futures.
+    pub fn step(&mut self) -> bool {
+        self.poll_step(&mut Context::from_waker(noop_waker_ref()))
+    }
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> bool {
+        for (rx, queued) in self.threads.iter_mut().zip(self.queued.iter_mut()) {
+            while let Poll::Ready(Some(task)) = rx.poll_recv(cx) {
+                queued.push_back(task);
+            }
+        }
+        let ready: Vec<usize> = (0..self.queued.len())
+            .filter(|&thread| !self.queued[thread].is_empty())
+            .collect();
+        if ready.is_empty() {
+            return false;
+        }
+        let thread = ready[self.order.next(ready.len())];
+        (self.queued[thread].pop_front().unwrap())();
+        true
+    }
+    /// Runs work until `f` completes, yielding to the [LocalSet] after each step.
+    pub async fn run_until<F: Future>(&mut self, f: F) -> F::Output {
+        let mut f = pin!(f);
+        poll_fn(|cx| {
+            if let Poll::Ready(output) = f.as_mut().poll(cx) {
+                return Poll::Ready(output);
+            }
+            if self.poll_step(cx) {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl ThreadLoad {
+    fn stats(&self) -> ThreadStats {
+        ThreadStats {
+            active_tasks: self.active.load(Ordering::Relaxed),
+            queued_tasks: self.queued.load(Ordering::Relaxed),
+            spawned_tasks: self.spawned.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl ActiveGuard {
+    fn new(load: &Arc<ThreadLoad>) -> Self {
+        load.active.fetch_add(1, Ordering::Relaxed);
+        ActiveGuard(load.clone())
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl LocalSetSpawn {
+    /// The thread with the fewest active and queued tasks. Ties are broken round-robin.
+    fn least_loaded(&self) -> &Worker {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.threads.len())
+            .map(|offset| &self.threads[(start + offset) % self.threads.len()])
+            .min_by_key(|worker| {
+                worker.load.active.load(Ordering::Relaxed)
+                    + worker.load.queued.load(Ordering::Relaxed)
+            })
+            .unwrap()
+    }
+    fn send<F: 'static + Sync + Send + FnOnce()>(worker: &Worker, f: F) {
+        let load = worker.load.clone();
+        load.queued.fetch_add(1, Ordering::Relaxed);
+        load.spawned.fetch_add(1, Ordering::Relaxed);
+        let task = Box::new({
+            let load = load.clone();
+            move || {
+                load.queued.fetch_sub(1, Ordering::Relaxed);
+                f()
+            }
+        });
+        if worker.tx.send(task).is_err() {
+            load.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
     pub fn spawn_fn<F: 'static + Sync + Send + FnOnce()>(&self, f: F) {
-        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len();
-        self.channels[index].send(Box::<F>::new(f)).ok();
+        Self::send(self.least_loaded(), f)
     }
     pub fn spawn_fut<Fu: 'static + Sync + Send + Future<Output = ()>>(&self, f: Fu) {
-        self.spawn_fn(|| {
-            task::spawn_local(f);
+        let worker = self.least_loaded();
+        let active = ActiveGuard::new(&worker.load);
+        Self::send(worker, || {
+            task::spawn_local(async move {
+                f.await;
+                mem::drop(active);
+            });
         })
     }
     pub fn spawn_async<F: 'static + Sync + Send + FnOnce() -> Fu, Fu: 'static + Future>(
@@ -80,23 +240,40 @@ impl LocalSetSpawn {
         Fu::Output: 'static + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.spawn_fn(|| {
+        let worker = self.least_loaded();
+        let active = ActiveGuard::new(&worker.load);
+        Self::send(worker, || {
             let fu = f();
             task::spawn_local(async move {
-                tx.send(fu.await).ok();
+                let output = fu.await;
+                mem::drop(active);
+                tx.send(output).ok();
             });
         });
         rx
     }
+    /// The load on each thread, for monitoring.
+    pub fn thread_stats(&self) -> Vec<ThreadStats> {
+        self.threads
+            .iter()
+            .map(|worker| worker.load.stats())
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{future::pending, mem};
+    use std::{future::pending, mem, sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
 
-    use tokio::sync::mpsc::{error::TryRecvError, unbounded_channel};
+    use tokio::{
+        sync::mpsc::{error::TryRecvError, unbounded_channel},
+        task::LocalSet,
+        time::{sleep, Instant},
+    };
 
-    use crate::local_set::LocalSetPool;
+    use crate::local_set::{DeterministicPool, LocalSetPool};
 
     #[tokio::test]
     async fn test() {
@@ -112,4 +289,76 @@ mod test {
         p.join().await.unwrap();
         assert!(rx.recv().await.is_none());
     }
+
+    #[tokio::test]
+    async fn test_least_loaded() {
+        let (s, mut p) = LocalSetPool::new(2);
+        s.spawn_async(pending::<()>);
+        for _ in 0..3 {
+            s.spawn_async(|| async {}).await.unwrap();
+        }
+        let stats = s.thread_stats();
+        assert_eq!(
+            stats.iter().map(|x| x.active_tasks).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+        assert_eq!(
+            stats.iter().map(|x| x.spawned_tasks).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        mem::drop(s);
+        p.join().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deterministic() {
+        let (s, mut p) = DeterministicPool::new(2, 0);
+        let start = Instant::now();
+        let slow = s.spawn_async(|| async {
+            sleep(Duration::from_secs(10)).await;
+            "slow"
+        });
+        let fast = s.spawn_async(|| async {
+            sleep(Duration::from_secs(1)).await;
+            "fast"
+        });
+        LocalSet::new()
+            .run_until(async move {
+                assert_eq!(p.run_until(fast).await.unwrap(), "fast");
+                assert_eq!(start.elapsed(), Duration::from_secs(1));
+                assert_eq!(p.run_until(slow).await.unwrap(), "slow");
+                assert_eq!(start.elapsed(), Duration::from_secs(10));
+            })
+            .await;
+    }
+
+    fn step_order(seed: u64) -> Vec<usize> {
+        let log = Arc::new(Mutex::new(vec![]));
+        let (s, mut p) = DeterministicPool::new(3, seed);
+        for index in 0..9 {
+            let log = log.clone();
+            s.spawn_fn(move || log.lock().push(index));
+        }
+        while p.step() {}
+        assert!(!p.step());
+        let log = log.lock().clone();
+        log
+    }
+
+    #[test]
+    fn test_deterministic_step() {
+        let orders: Vec<_> = (0..4).map(step_order).collect();
+        for (seed, order) in orders.iter().enumerate() {
+            assert_eq!(*order, step_order(seed as u64));
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..9).collect::<Vec<_>>());
+            // Work on each thread still runs in the order it was spawned.
+            for thread in 0..3 {
+                let on_thread: Vec<_> = order.iter().filter(|x| *x % 3 == thread).collect();
+                assert!(on_thread.windows(2).all(|x| x[0] < x[1]));
+            }
+        }
+        assert!(orders.iter().any(|order| *order != orders[0]));
+    }
 }
@@ -0,0 +1,274 @@
+//! Timers for tasks running on an [EventPool](crate::event_loop::EventPool). A task waiting on a
+//! timer is woken as a macrotask, so the messages it sent before waiting are flushed first, and the
+//! messages it sends after waking are flushed before any other macrotask runs.
+
+use std::{cell::RefCell, future::Future, rc::Rc, time::Duration};
+
+use futures::future::LocalBoxFuture;
+pub use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
+
+use octant_error::OctantResult;
+
+use crate::event_loop::{AbortOnDrop, EventSpawn};
+
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+pub async fn sleep_until(deadline: Instant) {
+    tokio::time::sleep_until(deadline).await
+}
+
+/// Ticks every `period`, starting immediately. Ticks missed while the task was busy are skipped
+/// rather than delivered in a burst: a late tick fires once, and the next stays on the original
+/// schedule.
+pub struct Interval(tokio::time::Interval);
+
+pub fn interval(period: Duration) -> Interval {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    Interval(interval)
+}
+
+impl Interval {
+    pub async fn tick(&mut self) -> Instant {
+        self.0.tick().await
+    }
+    pub fn period(&self) -> Duration {
+        self.0.period()
+    }
+}
+
+/// Runs an action once calls stop arriving for a while, e.g. a search after the user stops typing.
+pub struct Debounce {
+    spawn: Rc<EventSpawn>,
+    delay: Duration,
+    pending: RefCell<Option<AbortOnDrop<()>>>,
+}
+
+impl Debounce {
+    pub fn new(spawn: Rc<EventSpawn>, delay: Duration) -> Self {
+        Debounce {
+            spawn,
+            delay,
+            pending: RefCell::new(None),
+        }
+    }
+    /// Runs `f` after no other call has been made for the delay. The previous call is aborted,
+    /// whether it is still waiting or already running.
+    pub fn call<F: 'static + Future<Output = OctantResult<()>>>(&self, f: F) {
+        let delay = self.delay;
+        let task = self.spawn.spawn_macro(async move {
+            sleep(delay).await;
+            f.await
+        });
+        self.pending.replace(Some(task.abort_on_drop()));
+    }
+    /// Aborts the pending call, if any.
+    pub fn cancel(&self) {
+        self.pending.take();
+    }
+}
+
+/// Runs an action at most once per period, e.g. to save a document while it is being edited. Calls
+/// made while waiting replace each other, so only the latest runs at the end of the period.
+pub struct Throttle {
+    spawn: Rc<EventSpawn>,
+    state: Rc<RefCell<ThrottleState>>,
+    task: RefCell<Option<AbortOnDrop<()>>>,
+}
+
+struct ThrottleState {
+    period: Duration,
+    next: Option<Instant>,
+    pending: Option<LocalBoxFuture<'static, OctantResult<()>>>,
+    running: bool,
+}
+
+impl Throttle {
+    pub fn new(spawn: Rc<EventSpawn>, period: Duration) -> Self {
+        Throttle {
+            spawn,
+            state: Rc::new(RefCell::new(ThrottleState {
+                period,
+                next: None,
+                pending: None,
+                running: false,
+            })),
+            task: RefCell::new(None),
+        }
+    }
+    /// Runs `f` in the next macrotask if nothing has run in the last period, and otherwise when
+    /// the period ends.
+    pub fn call<F: 'static + Future<Output = OctantResult<()>>>(&self, f: F) {
+        let ref mut state = *self.state.borrow_mut();
+        state.pending = Some(Box::pin(f));
+        if !state.running {
+            state.running = true;
+            let task = self.spawn.spawn_macro(Self::run(self.state.clone()));
+            *self.task.borrow_mut() = Some(task.abort_on_drop());
+        }
+    }
+    /// Aborts the pending call, if any.
+    pub fn cancel(&self) {
+        self.state.borrow_mut().pending = None;
+    }
+    async fn run(state: Rc<RefCell<ThrottleState>>) -> OctantResult<()> {
+        loop {
+            let next = state.borrow().next;
+            if let Some(next) = next {
+                sleep_until(next).await;
+            }
+            let f = {
+                let ref mut state = *state.borrow_mut();
+                let Some(f) = state.pending.take() else {
+                    state.running = false;
+                    return Ok(());
+                };
+                state.next = Some(Instant::now() + state.period);
+                f
+            };
+            if let Err(e) = f.await {
+                state.borrow_mut().running = false;
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{mem, task::Poll, time::Duration};
+
+    use parking_lot::Mutex;
+
+    use octant_error::OctantResult;
+
+    use crate::{
+        event_loop::EventPool,
+        timer::{interval, sleep, Debounce, Instant, Throttle},
+    };
+
+    fn log(start: Instant, log: &Mutex<Vec<String>>, message: &str) {
+        log.lock().push(format!(
+            "{} {}",
+            (Instant::now() - start).as_millis(),
+            message
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let start = Instant::now();
+        let (spawn, mut pool) = EventPool::new(move |_| {
+            log(start, &LOG, "flush");
+            Poll::Ready(Ok(()))
+        });
+        spawn.spawn(async move {
+            let mut ticks = interval(Duration::from_secs(1));
+            for _ in 0..3 {
+                ticks.tick().await;
+                log(start, &LOG, "tick");
+            }
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec![
+                "0 tick",
+                "0 flush",
+                "1000 tick",
+                "1000 flush",
+                "2000 tick",
+                "2000 flush"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval_missed_tick() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let start = Instant::now();
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        spawn.spawn(async move {
+            let mut ticks = interval(Duration::from_secs(1));
+            ticks.tick().await;
+            log(start, &LOG, "tick");
+            sleep(Duration::from_millis(2500)).await;
+            for _ in 0..2 {
+                ticks.tick().await;
+                log(start, &LOG, "tick");
+            }
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec!["0 tick", "2500 tick", "3000 tick"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_debounce() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let start = Instant::now();
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let debounce = Debounce::new(spawn.clone(), Duration::from_millis(100));
+        spawn.spawn(async move {
+            for input in ["a", "ab", "abc"] {
+                debounce.call(async move {
+                    log(start, &LOG, input);
+                    Ok(())
+                });
+                sleep(Duration::from_millis(50)).await;
+            }
+            sleep(Duration::from_millis(200)).await;
+            debounce.call(async move {
+                log(start, &LOG, "abcd");
+                Ok(())
+            });
+            sleep(Duration::from_millis(200)).await;
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec!["200 abc", "450 abcd"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle() -> OctantResult<()> {
+        static LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+        let start = Instant::now();
+        let (spawn, mut pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+        let throttle = Throttle::new(spawn.clone(), Duration::from_millis(100));
+        spawn.spawn(async move {
+            for index in 0..6 {
+                throttle.call(async move {
+                    log(start, &LOG, &format!("save {}", index));
+                    Ok(())
+                });
+                sleep(Duration::from_millis(30)).await;
+            }
+            sleep(Duration::from_millis(200)).await;
+            Ok(())
+        });
+        mem::drop(spawn);
+        pool.run().await?;
+        assert_eq!(
+            LOG.lock().iter().collect::<Vec<_>>(),
+            vec!["0 save 0", "100 save 3", "200 save 5"]
+        );
+        Ok(())
+    }
+}
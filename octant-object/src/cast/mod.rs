@@ -51,7 +51,7 @@
 //! optimizations may not be very effective. The iterative process may make casts for deep
 //! hierarchies inefficient.
 use std::{
-    any::Any,
+    any::{type_name, Any},
     marker::Unsize,
     mem,
     ptr::{DynMetadata, Pointee},
@@ -102,6 +102,8 @@ pub mod smart_pointer;
 /// ```
 pub trait CastValue: 'static + Any {
     fn into_leaf(&self) -> fn(SmartPointer<dyn Any>) -> BoxCastObject;
+    /// The name of the most derived class of this object, without its module path.
+    fn class_name(&self) -> &'static str;
 }
 
 impl<T> CastValue for T
@@ -119,6 +121,12 @@ where
             ptr
         }
     }
+    fn class_name(&self) -> &'static str {
+        let name = type_name::<T::Dyn>();
+        let name = name.strip_prefix("dyn ").unwrap_or(name);
+        let name = name.split('<').next().unwrap();
+        name.rsplit("::").next().unwrap()
+    }
 }
 
 /// A trait implemented for `dyn T` where `T` is a class. If `T2` is the parent class of `T`,
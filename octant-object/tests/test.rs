@@ -11,7 +11,7 @@ use std::rc::Rc;
 use octant_object::{
     base,
     base::{Base, BaseFields},
-    cast::downcast_object,
+    cast::{downcast_object, CastValue},
 };
 use octant_object_derive::class;
 use marshal_pointer::RcfRef;
@@ -172,3 +172,13 @@ fn test_downcast() {
         let x: Rc<dyn D> = downcast_object(x).ok().unwrap();
     }
 }
+
+#[test]
+fn test_class_name() {
+    let x: Rc<dyn A> = Rc::new(DFields::new(1, 2, 3, 4));
+    assert_eq!(x.class_name(), "D");
+    let x: Rc<dyn A> = Rc::new(BFields::new(1, 2));
+    assert_eq!(x.class_name(), "B");
+    let x: &dyn Base = &BaseFields::new();
+    assert_eq!(x.class_name(), "Base");
+}
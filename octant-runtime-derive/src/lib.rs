@@ -4,11 +4,17 @@ use convert_case::{Case, Casing};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use syn::{
-    parse_macro_input, spanned::Spanned, Data, DataStruct, DeriveInput, FnArg, ImplItem,
-    ImplItemFn, Item, ItemFn, ItemImpl, Pat, ReturnType, Signature, Token, Type,
+    meta::ParseNestedMeta, parenthesized, parse::Parse, parse_macro_input, parse_quote,
+    punctuated::Punctuated, spanned::Spanned, token, Attribute, Data, DeriveInput, Expr, Fields,
+    FnArg, GenericArgument, GenericParam, Generics, Ident, ImplItem, Item, ItemFn, ItemImpl,
+    LitStr, Member, Meta, Pat, PatType, PathArguments, Receiver, ReturnType, Signature, Token,
+    Type,
 };
 
-#[proc_macro_derive(PeerNewClient)]
+/// Derives `PeerNew` on the client. The fields type may be a struct, tuple struct or enum, whose
+/// fields are marked with `#[peer(parent)]`, `#[peer(native)]` and `#[peer(default = ...)]`. See
+/// [peer_layout] for the defaults.
+#[proc_macro_derive(PeerNewClient, attributes(peer))]
 pub fn derive_peer_new_client(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     proc_macro::TokenStream::from(
@@ -17,64 +23,48 @@ pub fn derive_peer_new_client(input: proc_macro::TokenStream) -> proc_macro::Tok
 }
 
 fn derive_peer_new_client_impl(input: DeriveInput) -> syn::Result<TokenStream> {
-    let output: TokenStream;
-    let DeriveInput {
-        attrs: input_attrs,
-        vis: input_vis,
-        ident: input_ident,
-        generics: input_generics,
-        data: input_data,
-    } = &input;
-    match input_data {
-        Data::Struct(strukt) => {
-            let DataStruct {
-                struct_token,
-                fields,
-                semi_token,
-            } = strukt;
-            let parent_field = fields
-                .iter()
-                .nth(0)
-                .expect("first field")
-                .ident
-                .as_ref()
-                .unwrap();
-            let parent_type = &fields.iter().nth(0).expect("first field").ty;
-            let native_field = fields
-                .iter()
-                .nth(1)
-                .expect("second field")
-                .ident
-                .as_ref()
-                .unwrap();
-            let native_type = &fields.iter().nth(1).expect("second field").ty;
-            let field_names: Vec<_> = fields.iter().skip(2).map(|x| &x.ident).collect();
-            output = quote! {
-                impl ::octant_runtime_client::PeerNew for #input_ident {
-                    type Builder = #native_type;
-                    fn peer_new(native: #native_type) -> Self {
-                        #input_ident {
-                            #parent_field: <#parent_type as ::octant_runtime_client::PeerNew>::peer_new(::std::clone::Clone::clone(&native).into()),
-                            #native_field: native,
-                            #( #field_names : ::std::default::Default::default()),*
-                        }
-                    }
+    let input_ident = &input.ident;
+    let layouts = peer_variants(&input)?
+        .into_iter()
+        .map(|(path, fields)| peer_layout(path, fields, true))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let PeerLayout {
+        path,
+        parent: (parent_member, parent_type),
+        native,
+        rest,
+    } = &layouts[0];
+    let (native_member, native_type) = native.as_ref().unwrap();
+    let (rest_members, rest_values): (Vec<_>, Vec<_>) = rest.iter().cloned().unzip();
+    let native_arms = layouts.iter().map(|layout| {
+        let path = &layout.path;
+        let (native_member, _) = layout.native.as_ref().unwrap();
+        quote! { #path { #native_member: native, .. } => native }
+    });
+    Ok(quote! {
+        impl ::octant_runtime_client::PeerNew for #input_ident {
+            type Builder = #native_type;
+            fn peer_new(native: #native_type) -> Self {
+                #path {
+                    #parent_member: <#parent_type as ::octant_runtime_client::PeerNew>::peer_new(::std::clone::Clone::clone(&native).into()),
+                    #native_member: native,
+                    #( #rest_members : #rest_values),*
                 }
-                impl ::octant_runtime_client::peer::AsNative for <#input_ident as ::octant_object::class::ClassValue>::Dyn {
-                    type Native = #native_type;
-                    fn native(&self)->&#native_type{
-                        &self.#native_field
-                    }
+            }
+        }
+        impl ::octant_runtime_client::peer::AsNative for <#input_ident as ::octant_object::class::ClassValue>::Dyn {
+            type Native = #native_type;
+            fn native(&self)->&#native_type{
+                match &**self {
+                    #(#native_arms),*
                 }
-            };
+            }
         }
-        Data::Enum(_) => todo!(),
-        Data::Union(_) => todo!(),
-    }
-    Ok(output)
+    })
 }
 
-#[proc_macro_derive(PeerNewServer)]
+/// Derives `PeerNew` on the server, like [derive_peer_new_client] but without a native field.
+#[proc_macro_derive(PeerNewServer, attributes(peer))]
 pub fn derive_peer_new_server(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     proc_macro::TokenStream::from(
@@ -83,46 +73,191 @@ pub fn derive_peer_new_server(input: proc_macro::TokenStream) -> proc_macro::Tok
 }
 
 fn derive_peer_new_server_impl(input: DeriveInput) -> syn::Result<TokenStream> {
-    let output: TokenStream;
-    let DeriveInput {
-        attrs: input_attrs,
-        vis: input_vis,
-        ident: input_ident,
-        generics: input_generics,
-        data: input_data,
-    } = &input;
-    match input_data {
-        Data::Struct(strukt) => {
-            let DataStruct {
-                struct_token,
-                fields,
-                semi_token,
-            } = strukt;
-            let parent_field = fields
-                .iter()
-                .nth(0)
-                .expect("first field")
-                .ident
-                .as_ref()
-                .unwrap();
-            let parent_type = &fields.iter().nth(0).expect("first field").ty;
-            let field_names: Vec<_> = fields.iter().skip(1).map(|x| &x.ident).collect();
-            output = quote! {
-                impl ::octant_runtime_server::PeerNew for #input_ident {
-                    type Builder = ::octant_runtime::peer::PeerFields;
-                    fn peer_new(peer: ::octant_runtime::peer::PeerFields) -> Self {
-                        #input_ident {
-                            #parent_field: <#parent_type as ::octant_runtime_server::PeerNew>::peer_new(peer),
-                            #( #field_names : ::std::default::Default::default()),*
-                        }
+    let input_ident = &input.ident;
+    let (path, fields) = peer_variants(&input)?.remove(0);
+    let PeerLayout {
+        path,
+        parent: (parent_member, parent_type),
+        native: _,
+        rest,
+    } = peer_layout(path, fields, false)?;
+    let (rest_members, rest_values): (Vec<_>, Vec<_>) = rest.iter().cloned().unzip();
+    Ok(quote! {
+        impl ::octant_runtime_server::PeerNew for #input_ident {
+            type Builder = ::octant_runtime::peer::PeerFields;
+            fn peer_new(peer: ::octant_runtime::peer::PeerFields) -> Self {
+                #path {
+                    #parent_member: <#parent_type as ::octant_runtime_server::PeerNew>::peer_new(peer),
+                    #( #rest_members : #rest_values),*
+                }
+            }
+        }
+    })
+}
+
+/// The options in the `#[peer(...)]` attributes of a field or variant.
+#[derive(Default)]
+struct PeerAttrs {
+    parent: bool,
+    native: bool,
+    /// `Some(None)` for a bare `default`, which selects an enum variant.
+    default: Option<Option<Expr>>,
+}
+
+fn peer_attrs(attrs: &[Attribute]) -> syn::Result<PeerAttrs> {
+    let mut result = PeerAttrs::default();
+    for attr in attrs {
+        if !attr.path().is_ident("peer") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("parent") {
+                result.parent = true;
+            } else if meta.path.is_ident("native") {
+                result.native = true;
+            } else if meta.path.is_ident("default") {
+                if meta.input.peek(Token![=]) {
+                    result.default = Some(Some(meta.value()?.parse()?));
+                } else {
+                    result.default = Some(None);
+                }
+            } else {
+                return Err(meta.error("expected `parent`, `native` or `default`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+/// Lists the constructors of a peer's fields type with their fields. The first is the one `PeerNew`
+/// builds: the struct itself, or the only variant or the variant marked `#[peer(default)]`.
+fn peer_variants(input: &DeriveInput) -> syn::Result<Vec<(TokenStream, &Fields)>> {
+    let input_ident = &input.ident;
+    match &input.data {
+        Data::Struct(strukt) => Ok(vec![(quote!(#input_ident), &strukt.fields)]),
+        Data::Enum(enom) => {
+            let mut variants = vec![];
+            let mut default = None;
+            for variant in &enom.variants {
+                let attrs = peer_attrs(&variant.attrs)?;
+                if attrs.parent || attrs.native {
+                    return Err(syn::Error::new(
+                        variant.span(),
+                        "`parent` and `native` belong on fields",
+                    ));
+                }
+                if attrs.default.is_some() {
+                    if default.is_some() {
+                        return Err(syn::Error::new(
+                            variant.span(),
+                            "only one variant may be marked #[peer(default)]",
+                        ));
                     }
+                    default = Some(variants.len());
+                }
+                let variant_ident = &variant.ident;
+                variants.push((quote!(#input_ident::#variant_ident), &variant.fields));
+            }
+            let default = match (default, variants.len()) {
+                (Some(default), _) => default,
+                (None, 1) => 0,
+                (None, _) => {
+                    return Err(syn::Error::new(
+                        input.span(),
+                        "mark the variant to construct with #[peer(default)]",
+                    ))
                 }
             };
+            let first = variants.remove(default);
+            variants.insert(0, first);
+            Ok(variants)
         }
-        Data::Enum(_) => todo!(),
-        Data::Union(_) => todo!(),
+        Data::Union(_) => Err(syn::Error::new(
+            input.span(),
+            "PeerNew cannot be derived for unions",
+        )),
     }
-    Ok(output)
+}
+
+/// How `PeerNew` fills in one constructor of a peer's fields type.
+struct PeerLayout<'a> {
+    path: TokenStream,
+    parent: (Member, &'a Type),
+    /// Always present on the client and absent on the server.
+    native: Option<(Member, &'a Type)>,
+    rest: Vec<(Member, TokenStream)>,
+}
+
+/// Assigns roles to `fields`. The parent is the field marked `#[peer(parent)]`, or else the first
+/// other field. On the client, the native object is the field marked `#[peer(native)]`, or else the
+/// first field other than the parent. Any remaining field starts with its `#[peer(default = ...)]`
+/// or [Default::default].
+fn peer_layout(path: TokenStream, fields: &Fields, client: bool) -> syn::Result<PeerLayout<'_>> {
+    let mut members = vec![];
+    let mut parent = None;
+    let mut native = None;
+    for (index, field) in fields.iter().enumerate() {
+        let attrs = peer_attrs(&field.attrs)?;
+        if attrs.parent && parent.replace(index).is_some() {
+            return Err(syn::Error::new(field.span(), "duplicate parent field"));
+        }
+        if attrs.native {
+            if !client {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "native fields only exist on the client",
+                ));
+            }
+            if native.replace(index).is_some() {
+                return Err(syn::Error::new(field.span(), "duplicate native field"));
+            }
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(index.into()),
+        };
+        members.push((member, field, attrs));
+    }
+    let parent = parent
+        .or_else(|| (0..members.len()).find(|&x| Some(x) != native))
+        .ok_or_else(|| syn::Error::new(fields.span(), "expected a parent field"))?;
+    let native = if client {
+        Some(
+            native
+                .or_else(|| (0..members.len()).find(|&x| x != parent))
+                .ok_or_else(|| syn::Error::new(fields.span(), "expected a native field"))?,
+        )
+    } else {
+        None
+    };
+    if Some(parent) == native {
+        return Err(syn::Error::new(
+            members[parent].1.span(),
+            "the parent field cannot also be the native field",
+        ));
+    }
+    let mut rest = vec![];
+    for (index, (member, field, attrs)) in members.iter().enumerate() {
+        if index == parent || Some(index) == native {
+            if let Some(Some(_)) = &attrs.default {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "the parent and native fields cannot have a default",
+                ));
+            }
+        } else if let Some(Some(default)) = &attrs.default {
+            rest.push((member.clone(), quote!(#default)));
+        } else {
+            rest.push((member.clone(), quote!(::std::default::Default::default())));
+        }
+    }
+    Ok(PeerLayout {
+        path,
+        parent: (members[parent].0.clone(), &members[parent].1.ty),
+        native: native.map(|x| (members[x].0.clone(), &members[x].1.ty)),
+        rest,
+    })
 }
 
 #[proc_macro_derive(SerializePeer)]
@@ -206,8 +341,73 @@ fn derive_deserialize_peer_impl(input: DeriveInput) -> syn::Result<TokenStream>
     Ok(output)
 }
 
+#[derive(Default)]
 struct RpcArgs {
     self_type: Option<Type>,
+    /// The message is sent from the client to the server.
+    up: bool,
+    /// The type arguments of each message type registered for a generic function.
+    instances: Vec<Punctuated<Type, Token![,]>>,
+    /// The generics of the enclosing impl block, which come before those of the function.
+    impl_generics: Generics,
+    /// Makes the message a last-writer-wins setter, see `DownMessage::coalesce_key`.
+    coalesce: Option<RpcCoalesce>,
+    /// The `RawHandle` parameter naming the peer that a free function's message is addressed to.
+    handle: Option<Ident>,
+    /// The `Vec<RawHandle>` parameter of a message that deletes handles.
+    deletes: Option<Ident>,
+    /// The function implements a trait method, so both sides keep the signature as written, with
+    /// the output wrapped in an `OctantResult`.
+    trait_impl: bool,
+}
+
+/// The arguments of `#[rpc(coalesce(group = "...", params...))]`.
+#[derive(Default)]
+struct RpcCoalesce {
+    /// Names the state the setter overwrites, so that setters of the same state replace each
+    /// other. Defaults to the name of the function.
+    group: Option<LitStr>,
+    /// The parameters that select part of the state.
+    params: Vec<Ident>,
+}
+
+impl RpcArgs {
+    fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("self") {
+            self.self_type = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("up") {
+            self.up = true;
+        } else if meta.path.is_ident("instance") {
+            let content;
+            parenthesized!(content in meta.input);
+            self.instances
+                .push(content.parse_terminated(Type::parse, Token![,])?);
+        } else if meta.path.is_ident("coalesce") {
+            let mut coalesce = RpcCoalesce::default();
+            if meta.input.peek(token::Paren) {
+                meta.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("group") {
+                        coalesce.group = Some(meta.value()?.parse()?);
+                    } else if let Some(param) = meta.path.get_ident() {
+                        coalesce.params.push(param.clone());
+                    } else {
+                        return Err(meta.error("expected a parameter or `group = \"...\"`"));
+                    }
+                    Ok(())
+                })?;
+            }
+            self.coalesce = Some(coalesce);
+        } else if meta.path.is_ident("handle") {
+            self.handle = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("deletes") {
+            self.deletes = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(
+                "expected `up`, `instance(...)`, `coalesce(...)`, `handle = ...` or `deletes = ...`",
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[proc_macro_attribute]
@@ -215,34 +415,226 @@ pub fn rpc(
     args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let mut self_type = None;
-    let parser = syn::meta::parser(|meta| {
-        if meta.path.is_ident("self") {
-            meta.input.parse::<Token![=]>()?;
-            self_type = Some(meta.input.parse()?);
-            Ok(())
-        } else {
-            Err(syn::Error::new(meta.path.span(), "No parameters expected"))
-        }
-    });
+    let mut rpc_args = RpcArgs::default();
+    let parser = syn::meta::parser(|meta| rpc_args.parse_meta(meta));
     parse_macro_input!(args with parser);
-    let args = RpcArgs { self_type };
     let input = parse_macro_input!(input as Item);
     proc_macro::TokenStream::from(
-        rpc_item(&args, &input).unwrap_or_else(syn::Error::into_compile_error),
+        rpc_item(&rpc_args, &input).unwrap_or_else(syn::Error::into_compile_error),
     )
 }
 
 fn rpc_item(args: &RpcArgs, input: &Item) -> syn::Result<TokenStream> {
     match input {
-        Item::Fn(f) => rpc_fn(args, f),
+        Item::Fn(f) => {
+            let (request_type_def, output) = if args.up {
+                rpc_up_fn(args, f)?
+            } else {
+                rpc_fn(args, f)?
+            };
+            Ok(quote! {
+                #request_type_def
+                #output
+            })
+        }
         Item::Impl(i) => rpc_impl(args, i),
-        _ => todo!(),
+        item => Err(syn::Error::new(
+            item.span(),
+            "#[rpc] applies to functions and impl blocks",
+        )),
+    }
+}
+
+/// The parameters of an RPC function and the generics of its message type, after checking that the
+/// signature is one the rpc macro supports.
+struct RpcSignature<'a> {
+    receiver: Option<&'a Receiver>,
+    /// The typed parameters with their positions in the signature.
+    params: Vec<(usize, &'a PatType)>,
+    /// The index into [Self::params] of the `&Rc<Runtime>` parameter.
+    runtime: usize,
+    /// The generics of the impl block followed by those of the function.
+    generics: Generics,
+}
+
+fn rpc_signature<'a>(args: &RpcArgs, sig: &'a Signature) -> syn::Result<RpcSignature<'a>> {
+    if let Some(x) = &sig.constness {
+        return Err(syn::Error::new(
+            x.span(),
+            "#[rpc] functions cannot be const",
+        ));
+    }
+    if let Some(x) = &sig.asyncness {
+        return Err(syn::Error::new(
+            x.span(),
+            "#[rpc] functions cannot be async, return an OctantFuture instead",
+        ));
+    }
+    if let Some(x) = &sig.unsafety {
+        return Err(syn::Error::new(
+            x.span(),
+            "#[rpc] functions cannot be unsafe",
+        ));
+    }
+    if let Some(x) = &sig.abi {
+        return Err(syn::Error::new(
+            x.span(),
+            "#[rpc] functions cannot specify an ABI",
+        ));
+    }
+    if let Some(x) = &sig.variadic {
+        return Err(syn::Error::new(
+            x.span(),
+            "#[rpc] functions cannot be variadic",
+        ));
+    }
+    let mut generics = args.impl_generics.clone();
+    generics.params.extend(sig.generics.params.iter().cloned());
+    if let Some(where_clause) = &sig.generics.where_clause {
+        generics
+            .make_where_clause()
+            .predicates
+            .extend(where_clause.predicates.iter().cloned());
+    }
+    for param in &generics.params {
+        if !matches!(param, GenericParam::Type(_)) {
+            return Err(syn::Error::new(
+                param.span(),
+                "#[rpc] functions can only be generic over types",
+            ));
+        }
+        if args.trait_impl {
+            return Err(syn::Error::new(
+                param.span(),
+                "#[rpc] functions in trait impls cannot be generic",
+            ));
+        }
+    }
+    let type_params = generics.params.len();
+    if type_params == 0 {
+        if let Some(instance) = args.instances.first() {
+            return Err(syn::Error::new(
+                instance.span(),
+                "only generic #[rpc] functions have instances",
+            ));
+        }
+    } else if args.instances.is_empty() {
+        return Err(syn::Error::new(
+            sig.ident.span(),
+            "generic #[rpc] functions must list the message types to register, e.g. #[rpc(instance(u32))]",
+        ));
+    }
+    for instance in &args.instances {
+        if instance.len() != type_params {
+            return Err(syn::Error::new(
+                instance.span(),
+                format!("each instance needs {} type arguments", type_params),
+            ));
+        }
+    }
+    let mut receiver = None;
+    let mut params = vec![];
+    let mut runtime = None;
+    for (position, input) in sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Receiver(x) => {
+                if args.self_type.is_none() {
+                    return Err(syn::Error::new(
+                        x.span(),
+                        "#[rpc] methods must be in an #[rpc] impl block",
+                    ));
+                }
+                receiver = Some(x);
+            }
+            FnArg::Typed(pat_type) => {
+                if is_runtime_type(&pat_type.ty) {
+                    if runtime.replace(params.len()).is_some() {
+                        return Err(syn::Error::new(
+                            pat_type.span(),
+                            "duplicate runtime parameter",
+                        ));
+                    }
+                } else if let Type::ImplTrait(ty) = &*pat_type.ty {
+                    return Err(syn::Error::new(
+                        ty.span(),
+                        "#[rpc] parameters cannot use `impl Trait`, add a type parameter instead",
+                    ));
+                } else if let Type::Reference(ty) = &*pat_type.ty {
+                    return Err(syn::Error::new(
+                        ty.span(),
+                        "#[rpc] parameters are sent to the other side, so they cannot be references",
+                    ));
+                }
+                params.push((position, pat_type));
+            }
+        }
+    }
+    let runtime = runtime.ok_or_else(|| {
+        syn::Error::new(
+            sig.ident.span(),
+            "#[rpc] functions take a `&Rc<Runtime>` parameter",
+        )
+    })?;
+    Ok(RpcSignature {
+        receiver,
+        params,
+        runtime,
+        generics,
+    })
+}
+
+/// Whether `ty` is `&Rc<Runtime>`, the type of the runtime parameter of an RPC function.
+fn is_runtime_type(ty: &Type) -> bool {
+    let Type::Reference(reference) = ty else {
+        return false;
+    };
+    let Type::Path(rc) = &*reference.elem else {
+        return false;
+    };
+    let Some(rc) = rc.path.segments.last() else {
+        return false;
+    };
+    let PathArguments::AngleBracketed(rc_args) = &rc.arguments else {
+        return false;
+    };
+    rc.ident == "Rc"
+        && matches!(
+            rc_args.args.first(),
+            Some(GenericArgument::Type(Type::Path(runtime)))
+                if runtime.path.segments.last().is_some_and(|x| x.ident == "Runtime")
+        )
+}
+
+/// Lists the message types to register for an RPC: the request type itself, or one instance per
+/// `#[rpc(instance(...))]` of a generic function. Each comes with the turbofish that calls the
+/// function at that instance.
+fn rpc_instances(
+    args: &RpcArgs,
+    sig: &Signature,
+    request_type: &Ident,
+) -> Vec<(TokenStream, TokenStream)> {
+    if args.instances.is_empty() {
+        return vec![(quote!(#request_type), quote!())];
     }
+    let own_params = sig.generics.params.len();
+    args.instances
+        .iter()
+        .map(|instance| {
+            let types: Vec<_> = instance.iter().collect();
+            let own_types = &types[types.len() - own_params..];
+            let turbofish = if own_types.is_empty() {
+                quote!()
+            } else {
+                quote!(::<#(#own_types),*>)
+            };
+            (quote!(#request_type<#(#types),*>), turbofish)
+        })
+        .collect()
 }
 
-fn rpc_fn(args: &RpcArgs, input: &ItemFn) -> syn::Result<TokenStream> {
-    let output_tokens: TokenStream;
+/// Generates a message sent by the server and run on the client. Returns the message type
+/// separately so that [rpc_impl] can define it outside the impl block.
+fn rpc_fn(args: &RpcArgs, input: &ItemFn) -> syn::Result<(TokenStream, TokenStream)> {
     let ItemFn {
         attrs,
         vis,
@@ -250,76 +642,135 @@ fn rpc_fn(args: &RpcArgs, input: &ItemFn) -> syn::Result<TokenStream> {
         block,
     } = input;
     let Signature {
-        constness,
-        asyncness,
-        unsafety,
-        abi,
         fn_token,
         ident,
         generics,
-        paren_token,
         inputs,
-        variadic,
         output,
+        ..
     } = sig;
+    let rpc_sig = rpc_signature(args, sig)?;
     let mut server_params: Vec<TokenStream> = vec![];
-    let mut param_names: Vec<TokenStream> = vec![];
-    let mut seen_runtime = false;
-    let mut server_runtime_param = vec![];
+    let mut param_fields: Vec<TokenStream> = vec![];
+    let mut field_names = vec![];
+    let mut field_inits = vec![];
+    let mut call_args = vec![];
+    for (index, &(position, pat_type)) in rpc_sig.params.iter().enumerate() {
+        let colon = &pat_type.colon_token;
+        let ty = &pat_type.ty;
+        if index == rpc_sig.runtime {
+            if rpc_sig.receiver.is_none() || args.trait_impl {
+                let runtime = format_ident!("runtime", span = pat_type.pat.span());
+                server_params.push(quote! { #runtime #colon #ty });
+            }
+            call_args.push(quote! { runtime });
+        } else {
+            // The server function names its parameters apart from its own locals. The message
+            // names its fields after the parameters, unless they would clash with its own fields.
+            let (name, param_name) = match &*pat_type.pat {
+                Pat::Ident(ident) => (ident.ident.clone(), format_ident!("_param_{}", ident.ident)),
+                _ => {
+                    let name = format_ident!("_param_{}", position, span = pat_type.pat.span());
+                    (name.clone(), name)
+                }
+            };
+            let field_name = if name == "this" || name == "down" {
+                param_name.clone()
+            } else {
+                name.clone()
+            };
+            server_params.push(quote! { #param_name #colon #ty });
+            param_fields.push(quote! { pub #field_name #colon #ty });
+            call_args.push(quote! { self.#field_name });
+            field_inits.push(quote! { #field_name: #param_name });
+            field_names.push((name, field_name));
+        }
+    }
     let mut self_param = vec![];
     let mut runtime_lookup = vec![];
     let mut this_capture = vec![];
     let mut this_field = vec![];
     let mut self_callee = vec![];
-    for (i, input) in inputs.iter().enumerate() {
-        match input {
-            FnArg::Receiver(rec) => {
-                self_param.push(quote! { #rec });
+    let mut handle_fn = vec![];
+    if let Some(rec) = rpc_sig.receiver {
+        let self_type = &args.self_type;
+        self_param.push(quote! { #rec });
+        if !args.trait_impl {
+            runtime_lookup.push(quote! {
+                let runtime = self.runtime();
+            });
+        }
+        this_capture.push(quote! {
+            this: ::octant_runtime::reexports::marshal_pointer::Rcf::from(self.strong())
+        });
+        this_field.push(quote! {
+            pub this: ::octant_runtime::reexports::marshal_pointer::Rcf<#self_type>
+        });
+        self_callee.push(quote! { self.this. });
+        handle_fn.push(quote! {
+            fn handle(&self) -> ::std::option::Option<::octant_runtime::handle::RawHandle> {
+                ::std::option::Option::Some(self.this.raw_handle())
             }
-            FnArg::Typed(pat_type) => {
-                let colon = &pat_type.colon_token;
-                let ty = Some(&pat_type.ty);
-                if !seen_runtime {
-                    seen_runtime = true;
-                    let ident = Some(format_ident!("runtime", span = pat_type.pat.span()));
-                    if self_param.is_empty() {
-                        server_runtime_param.push(quote! {#ident #colon #ty });
-                    } else {
-                        let self_type = args.self_type.as_ref().ok_or_else(|| {
-                            syn::Error::new(
-                                pat_type.span(),
-                                "Must specify [rpc(self=TheCurrentClass)] for methods",
-                            )
-                        })?;
-                        runtime_lookup.push(quote! {
-                            let runtime = self.runtime();
-                        });
-                        this_capture.push(quote! {
-                            this: ::octant_runtime::reexports::marshal_pointer::Rcf::from(self.strong())
-                        });
-                        this_field.push(quote! {
-                            this: ::octant_runtime::reexports::marshal_pointer::Rcf<#self_type>
-                        });
-                        self_callee.push(quote! {
-                            //<::std::rc::Rc<_> as ::octant_runtime::reexports::marshal_pointer::AsFlatRef>::as_flat_ref(&self.this).
-                            self.this.
-                        });
-                    }
-                } else {
-                    let param_name;
-                    match &*pat_type.pat {
-                        Pat::Ident(ident) => {
-                            param_name = format_ident!("_param_{}", ident.ident);
-                        }
-                        _ => {
-                            param_name = format_ident!("_param_{}", i, span = pat_type.pat.span());
-                        }
-                    }
-                    server_params.push(quote! { #param_name #colon #ty });
-                    param_names.push(quote! {#param_name});
-                }
+        });
+    }
+    let field_for = |param: &Ident| {
+        field_names
+            .iter()
+            .find(|(name, _)| name == param)
+            .map(|(_, field)| field)
+            .ok_or_else(|| syn::Error::new(param.span(), "no such parameter"))
+    };
+    if let Some(handle) = &args.handle {
+        if rpc_sig.receiver.is_some() {
+            return Err(syn::Error::new(
+                handle.span(),
+                "methods are addressed to their receiver",
+            ));
+        }
+        let field = field_for(handle)?;
+        handle_fn.push(quote! {
+            fn handle(&self) -> ::std::option::Option<::octant_runtime::handle::RawHandle> {
+                ::std::option::Option::Some(self.#field)
+            }
+        });
+    }
+    let mut message_fns = vec![];
+    if let Some(deletes) = &args.deletes {
+        let field = field_for(deletes)?;
+        message_fns.push(quote! {
+            fn deleted_handles(&self) -> &[::octant_runtime::handle::RawHandle] {
+                &self.#field
+            }
+        });
+    }
+    if let Some(coalesce) = &args.coalesce {
+        if rpc_sig.receiver.is_none() {
+            return Err(syn::Error::new(
+                ident.span(),
+                "only methods can be coalesced",
+            ));
+        }
+        let group = match &coalesce.group {
+            Some(group) => quote! { #group },
+            None => {
+                let group = ident.to_string();
+                quote! { #group }
             }
         };
+        let fields = coalesce
+            .params
+            .iter()
+            .map(field_for)
+            .collect::<syn::Result<Vec<_>>>()?;
+        message_fns.push(quote! {
+            fn coalesce_key(&self) -> ::std::option::Option<::octant_runtime::proto::CoalesceKey<'_>> {
+                ::std::option::Option::Some(::octant_runtime::proto::CoalesceKey {
+                    handle: self.this.raw_handle(),
+                    property: #group,
+                    arguments: ::std::vec![#(&self.#fields as &dyn ::octant_runtime::proto::CoalesceArgument),*],
+                })
+            }
+        });
     }
     let output_type;
     let output_type_arrow;
@@ -340,54 +791,268 @@ fn rpc_fn(args: &RpcArgs, input: &ItemFn) -> syn::Result<TokenStream> {
             .to_case(Case::Pascal),
         span = ident.span()
     );
+    let generic = !rpc_sig.generics.params.is_empty();
+    let (impl_generics, ty_generics, where_clause) = rpc_sig.generics.split_for_impl();
+    let mut message_impls = vec![];
+    for (message_type, turbofish) in rpc_instances(args, sig, &request_type) {
+        message_impls.push(quote! {
+            ::octant_runtime::reexports::marshal_object::derive_variant!(::octant_runtime::proto::BoxDownMessage, #message_type);
+            ::octant_runtime::register_message!(#message_type);
+            #[cfg(side = "server")]
+            impl ::octant_runtime::proto::DownMessage for #message_type {
+                #(#handle_fn)*
+                #(#message_fns)*
+                fn mirror(
+                    &self,
+                    runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime>,
+                    peers: &mut ::std::vec::Vec<::octant_runtime::reexports::marshal_pointer::Rcf<dyn ::octant_runtime::peer::Peer>>,
+                ) {
+                    self.immediate_mirror(runtime, peers)
+                }
+            }
+            #[cfg(side = "client")]
+            impl ::octant_runtime::proto::DownMessage for #message_type {
+                fn run(self:Box<Self>, runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime>) -> ::octant_runtime::reexports::octant_error::OctantResult<()>{
+                    let output = #(#self_callee)*#ident #turbofish(#(#call_args),*)?;
+                    ::octant_runtime::immediate_return::ImmediateReturn::immediate_return(output, runtime, self.down);
+                    Ok(())
+                }
+                #(#handle_fn)*
+            }
+        });
+    }
+    // A generic message type is only a DownMessage at its instances.
     let request_type_def = quote! {
         #[derive(::std::fmt::Debug, ::octant_runtime::reexports::marshal::Serialize, ::octant_runtime::reexports::marshal::Deserialize)]
-        struct #request_type {
+        #vis struct #request_type #impl_generics #where_clause {
             #(#this_field,)*
-            #(#server_params,)*
-            down: <#output_type as ::octant_runtime::immediate_return::ImmediateReturn>::Down
+            #(#param_fields,)*
+            pub down: <#output_type as ::octant_runtime::immediate_return::ImmediateReturn>::Down
         }
-        ::octant_runtime::reexports::marshal_object::derive_variant!(::octant_runtime::proto::BoxDownMessage, #request_type);
+        #[cfg(side = "server")]
+        impl #impl_generics #request_type #ty_generics #where_clause {
+            fn immediate_mirror(
+                &self,
+                runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime>,
+                peers: &mut ::std::vec::Vec<::octant_runtime::reexports::marshal_pointer::Rcf<dyn ::octant_runtime::peer::Peer>>,
+            ) {
+                <#output_type as ::octant_runtime::immediate_return::ImmediateReturn>::immediate_mirror(runtime, &self.down, peers)
+            }
+        }
+        #(#message_impls)*
+    };
+    let mut server_generics = generics.clone();
+    if generic {
+        server_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! {
+                #request_type #ty_generics: ::octant_runtime::proto::DownMessage
+            });
+    }
+    let server_where_clause = &server_generics.where_clause;
+    let client_where_clause = &generics.where_clause;
+    let (server_output, server_return) = if args.trait_impl {
+        (
+            quote! { #output_type_arrow ::octant_runtime::reexports::octant_error::OctantResult<#output_type> },
+            quote! { ::std::result::Result::Ok(output) },
+        )
+    } else {
+        (quote! { #output }, quote! { output })
     };
-    output_tokens = quote! {
+    let output_tokens = quote! {
+        #(#attrs)*
         #[cfg(side = "server")]
-        #vis #fn_token #ident(
+        #vis #fn_token #ident #generics (
             #(#self_param,)*
-            #(#server_runtime_param,)*
             #(#server_params),*
-        ) #output {
-            #request_type_def
-            impl ::octant_runtime::proto::DownMessage for #request_type {}
-
+        ) #server_output #server_where_clause {
             #(#runtime_lookup)*
             let (output, down) = <#output_type as ::octant_runtime_server::immediate_return::ImmediateReturn>::immediate_new(runtime);
-            runtime.send(Box::<#request_type>::new(#request_type {
+            runtime.send(Box::<#request_type #ty_generics>::new(#request_type {
                 #(#this_capture,)*
-                #(#param_names,)*
+                #(#field_inits,)*
                 down
             }));
-            output
+            #server_return
         }
 
 
+        #(#attrs)*
         #[cfg(side="client")]
-        #fn_token #ident(
+        #fn_token #ident #generics (
             #inputs
-        ) #output_type_arrow ::octant_runtime::reexports::octant_error::OctantResult<#output_type> {
-            #request_type_def
-            impl ::octant_runtime::proto::DownMessage for #request_type {
-                fn run(self:Box<Self>, runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime>) -> ::octant_runtime::reexports::octant_error::OctantResult<()>{
-                    let output = #(#self_callee)*#ident(runtime #(, self.#param_names)*)?;
-                    ::octant_runtime::immediate_return::ImmediateReturn::immediate_return(output, runtime, self.down);
-                    Ok(())
+        ) #output_type_arrow ::octant_runtime::reexports::octant_error::OctantResult<#output_type> #client_where_clause {
+            #block
+        }
+    };
+    Ok((request_type_def, output_tokens))
+}
+
+/// Generates a message sent by the client and run on the server. Returns the message type
+/// separately so that [rpc_impl] can define it outside the impl block.
+fn rpc_up_fn(args: &RpcArgs, input: &ItemFn) -> syn::Result<(TokenStream, TokenStream)> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = input;
+    let Signature {
+        fn_token,
+        ident,
+        generics,
+        output,
+        ..
+    } = sig;
+    if let ReturnType::Type(_, ty) = output {
+        return Err(syn::Error::new(
+            ty.span(),
+            "#[rpc(up)] functions cannot return a value",
+        ));
+    }
+    if args.coalesce.is_some() || args.handle.is_some() || args.deletes.is_some() {
+        return Err(syn::Error::new(
+            ident.span(),
+            "`coalesce`, `handle` and `deletes` only apply to messages sent by the server",
+        ));
+    }
+    let rpc_sig = rpc_signature(args, sig)?;
+    let mut server_params = vec![];
+    let mut client_params = vec![];
+    let mut param_fields = vec![];
+    let mut param_names = vec![];
+    let mut call_args = vec![];
+    for (index, &(_, pat_type)) in rpc_sig.params.iter().enumerate() {
+        if index == rpc_sig.runtime {
+            server_params.push(quote! { #pat_type });
+            if rpc_sig.receiver.is_none() {
+                client_params
+                    .push(quote! { runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime> });
+            } else if args.trait_impl {
+                let ty = &pat_type.ty;
+                client_params.push(quote! { _: #ty });
+            }
+            call_args.push(quote! { runtime });
+        } else {
+            let colon = &pat_type.colon_token;
+            let ty = &pat_type.ty;
+            let Pat::Ident(param_name) = &*pat_type.pat else {
+                return Err(syn::Error::new(
+                    pat_type.pat.span(),
+                    "#[rpc(up)] parameters must be named",
+                ));
+            };
+            let param_name = &param_name.ident;
+            server_params.push(quote! { #param_name #colon #ty });
+            client_params.push(quote! { #param_name #colon #ty });
+            param_fields.push(quote! { pub #param_name #colon #ty });
+            call_args.push(quote! { self.#param_name });
+            param_names.push(param_name);
+        }
+    }
+    let mut self_param = vec![];
+    let mut this_capture = vec![];
+    let mut this_field = vec![];
+    let mut self_callee = vec![];
+    let mut handle_fn = vec![];
+    if let Some(rec) = rpc_sig.receiver {
+        let self_type = &args.self_type;
+        self_param.push(quote! { #rec });
+        this_capture.push(quote! {
+            this: ::octant_runtime::reexports::marshal_pointer::Rcf::from(self.strong())
+        });
+        this_field.push(quote! {
+            pub this: ::octant_runtime::reexports::marshal_pointer::Rcf<#self_type>
+        });
+        self_callee.push(quote! { self.this. });
+        handle_fn.push(quote! {
+            #[cfg(side = "server")]
+            fn handle(&self) -> ::std::option::Option<::octant_runtime::handle::RawHandle> {
+                ::std::option::Option::Some(self.this.raw_handle())
+            }
+        });
+    }
+    let request_type = format_ident!(
+        "{}Request",
+        format!("{}", ident)
+            .from_case(Case::Snake)
+            .to_case(Case::Pascal),
+        span = ident.span()
+    );
+    let (impl_generics, ty_generics, where_clause) = rpc_sig.generics.split_for_impl();
+    let mut message_impls = vec![];
+    for (message_type, turbofish) in rpc_instances(args, sig, &request_type) {
+        message_impls.push(quote! {
+            ::octant_runtime::reexports::marshal_object::derive_variant!(::octant_runtime::proto::BoxUpMessage, #message_type);
+            ::octant_runtime::register_message!(#message_type);
+            impl ::octant_runtime::proto::UpMessage for #message_type {
+                #[cfg(side = "server")]
+                fn run(self: Box<Self>, runtime: &::std::rc::Rc<::octant_runtime::runtime::Runtime>) -> ::octant_runtime::reexports::octant_error::OctantResult<()> {
+                    #(#self_callee)*#ident #turbofish(#(#call_args),*)
                 }
+                #(#handle_fn)*
             }
-            #block
+        });
+    }
+    let request_type_def = quote! {
+        #[derive(::std::fmt::Debug, ::octant_runtime::reexports::marshal::Serialize, ::octant_runtime::reexports::marshal::Deserialize)]
+        #vis struct #request_type #impl_generics #where_clause {
+            #(#this_field,)*
+            #(#param_fields,)*
         }
+        #(#message_impls)*
+    };
+    let client_send = if self_param.is_empty() {
+        quote! { runtime.sink() }
+    } else {
+        quote! { self.sink() }
+    };
+    let mut client_generics = generics.clone();
+    if !rpc_sig.generics.params.is_empty() {
+        client_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! {
+                #request_type #ty_generics: ::octant_runtime::proto::UpMessage
+            });
+    }
+    let server_where_clause = &generics.where_clause;
+    let client_where_clause = &client_generics.where_clause;
+    let (client_output, client_return) = if args.trait_impl {
+        (
+            quote! { -> ::octant_runtime::reexports::octant_error::OctantResult<()> },
+            quote! { ::std::result::Result::Ok(()) },
+        )
+    } else {
+        (quote! {}, quote! {})
     };
-    Ok(output_tokens)
+    let output_tokens = quote! {
+        #(#attrs)*
+        #[cfg(side = "server")]
+        #vis #fn_token #ident #generics (
+            #(#self_param,)*
+            #(#server_params),*
+        ) -> ::octant_runtime::reexports::octant_error::OctantResult<()> #server_where_clause #block
+
+        #(#attrs)*
+        #[cfg(side = "client")]
+        #vis #fn_token #ident #generics (
+            #(#self_param,)*
+            #(#client_params),*
+        ) #client_output #client_where_clause {
+            #client_send.send(::std::boxed::Box::<#request_type #ty_generics>::new(#request_type {
+                #(#this_capture,)*
+                #(#param_names,)*
+            }));
+            #client_return
+        }
+    };
+    Ok((request_type_def, output_tokens))
 }
 
+/// Expands the `#[rpc]` and `#[rpc(up)]` functions of an inherent or trait impl block. Message
+/// types that cannot be defined inside their functions are placed before the block.
 fn rpc_impl(args: &RpcArgs, input: &ItemImpl) -> syn::Result<TokenStream> {
     let ItemImpl {
         attrs,
@@ -400,53 +1065,280 @@ fn rpc_impl(args: &RpcArgs, input: &ItemImpl) -> syn::Result<TokenStream> {
         brace_token,
         items,
     } = input;
-    let output;
     let mut out_items = vec![];
+    let mut request_type_defs = vec![];
     for item in items {
-        match item {
-            ImplItem::Fn(item) => {
-                let ImplItemFn {
-                    attrs,
-                    vis,
-                    defaultness,
-                    sig,
-                    block,
-                } = item;
-                let mut out_attrs: Vec<TokenStream> = vec![];
-                for attr in &item.attrs {
-                    if attr.meta.path().is_ident("rpc") {
-                        out_attrs.push(quote! {
-                            #[rpc(self=#self_ty)]
-                        });
-                    } else {
-                        out_attrs.push(quote! {
-                            #attr
-                        })
-                    }
-                }
-                out_items.push(quote! {
-                    #(#out_attrs)*
-                    #vis
-                    #defaultness
-                    #sig
-                    #block
-                });
-            }
-            _ => {
-                out_items.push(quote! {#item});
+        let ImplItem::Fn(item) = item else {
+            out_items.push(quote! {#item});
+            continue;
+        };
+        let (rpc_attrs, fn_attrs): (Vec<_>, Vec<_>) = item
+            .attrs
+            .iter()
+            .cloned()
+            .partition(|attr| attr.path().is_ident("rpc"));
+        if rpc_attrs.is_empty() {
+            out_items.push(quote! {#item});
+            continue;
+        }
+        let mut fn_args = RpcArgs {
+            self_type: Some((**self_ty).clone()),
+            impl_generics: generics.clone(),
+            trait_impl: trait_.is_some(),
+            ..RpcArgs::default()
+        };
+        for attr in &rpc_attrs {
+            if let Meta::List(_) = &attr.meta {
+                attr.parse_nested_meta(|meta| fn_args.parse_meta(meta))?;
             }
         }
+        let item = ItemFn {
+            attrs: fn_attrs,
+            vis: item.vis.clone(),
+            sig: item.sig.clone(),
+            block: Box::new(item.block.clone()),
+        };
+        let (request_type_def, out_item) = if fn_args.up {
+            rpc_up_fn(&fn_args, &item)?
+        } else {
+            rpc_fn(&fn_args, &item)?
+        };
+        request_type_defs.push(request_type_def);
+        out_items.push(out_item);
     }
-    output = quote! {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let trait_ = trait_
+        .as_ref()
+        .map(|(bang, path, for_token)| quote! { #bang #path #for_token });
+    Ok(quote! {
+        #(#request_type_defs)*
         #(#attrs)*
         #defaultness
         #unsafety
         #impl_token
-        #generics
+        #impl_generics
+        #trait_
         #self_ty
+        #where_clause
         {
             #(#out_items)*
         }
-    };
-    Ok(output)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use quote::ToTokens;
+    use syn::{parse_quote, FnArg, ImplItem, Item, ReturnType};
+
+    use crate::{rpc_item, RpcArgs};
+
+    /// Whether an item with these attributes is compiled on `side`.
+    fn on_side(attrs: &[syn::Attribute], side: &str) -> bool {
+        attrs.iter().all(|attr| {
+            !attr.path().is_ident("cfg")
+                || attr.meta.to_token_stream().to_string() == format!("cfg (side = \"{}\")", side)
+        })
+    }
+
+    /// The parameter and output types of the functions in the impl of `Greet` that `input` expands
+    /// to, as compiled on `side`.
+    fn trait_signatures(input: &Item, side: &str) -> Vec<(String, Vec<String>, String)> {
+        let output: syn::File = syn::parse2(rpc_item(&RpcArgs::default(), input).unwrap()).unwrap();
+        let mut signatures = vec![];
+        for item in output.items {
+            let Item::Impl(item) = item else { continue };
+            let greet = matches!(&item.trait_, Some((_, path, _)) if path.is_ident("Greet"));
+            if !greet || !on_side(&item.attrs, side) {
+                continue;
+            }
+            for item in item.items {
+                let ImplItem::Fn(item) = item else { continue };
+                if !on_side(&item.attrs, side) {
+                    continue;
+                }
+                let params = item
+                    .sig
+                    .inputs
+                    .iter()
+                    .map(|input| match input {
+                        FnArg::Receiver(x) => x.ty.to_token_stream().to_string(),
+                        FnArg::Typed(x) => x.ty.to_token_stream().to_string(),
+                    })
+                    .collect();
+                let output = match &item.sig.output {
+                    ReturnType::Default => "()".to_owned(),
+                    ReturnType::Type(_, ty) => ty.to_token_stream().to_string(),
+                };
+                signatures.push((item.sig.ident.to_string(), params, output));
+            }
+        }
+        signatures
+    }
+
+    fn error(args: &RpcArgs, input: &Item) -> String {
+        rpc_item(args, input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_trait_impl() {
+        let input: Item = parse_quote! {
+            impl Greet for dyn Greeter {
+                #[rpc]
+                fn greet(self: &RcfRef<Self>, runtime: &Rc<Runtime>, name: String) -> u32 {
+                    Ok(1)
+                }
+                #[rpc(up)]
+                fn greeted(self: &RcfRef<Self>, _: &Rc<Runtime>, name: String) {
+                    Ok(())
+                }
+            }
+        };
+        let server = trait_signatures(&input, "server");
+        let client = trait_signatures(&input, "client");
+        assert_eq!(server, client);
+        let result = |x: &str| {
+            format!(
+                ":: octant_runtime :: reexports :: octant_error :: OctantResult < {} >",
+                x
+            )
+        };
+        let params = vec![
+            "& RcfRef < Self >".to_owned(),
+            "& Rc < Runtime >".to_owned(),
+            "String".to_owned(),
+        ];
+        assert_eq!(
+            server,
+            vec![
+                ("greet".to_owned(), params.clone(), result("u32")),
+                ("greeted".to_owned(), params, result("()")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instances() {
+        let input: Item = parse_quote! {
+            pub fn echo<T: Clone>(runtime: &Rc<Runtime>, value: T) {
+                Ok(())
+            }
+        };
+        let args = RpcArgs {
+            instances: vec![parse_quote!(u32), parse_quote!(String)],
+            ..RpcArgs::default()
+        };
+        let output = rpc_item(&args, &input).unwrap().to_string();
+        assert!(output.contains("register_message ! (EchoRequest < u32 >)"));
+        assert!(output.contains("register_message ! (EchoRequest < String >)"));
+        assert!(
+            error(&RpcArgs::default(), &input).starts_with("generic #[rpc] functions must list")
+        );
+        let args = RpcArgs {
+            instances: vec![parse_quote!(u32, u32)],
+            ..RpcArgs::default()
+        };
+        assert_eq!(error(&args, &input), "each instance needs 1 type arguments");
+    }
+
+    #[test]
+    fn test_field_names() {
+        let input: Item = parse_quote! {
+            pub fn credit(runtime: &Rc<Runtime>, stream: RawHandle, down: Vec<u8>) {
+                Ok(())
+            }
+        };
+        let args = RpcArgs {
+            handle: Some(parse_quote!(stream)),
+            ..RpcArgs::default()
+        };
+        let output = rpc_item(&args, &input).unwrap().to_string();
+        assert!(output.contains("pub stream : RawHandle"));
+        assert!(output.contains("pub _param_down : Vec < u8 >"));
+        assert!(output.contains("Some (self . stream)"));
+    }
+
+    #[test]
+    fn test_visibility() {
+        for up in [false, true] {
+            let args = RpcArgs {
+                up,
+                ..RpcArgs::default()
+            };
+            let private: Item = parse_quote! {
+                fn close(runtime: &Rc<Runtime>, stream: RawHandle) {
+                    Ok(())
+                }
+            };
+            let output = rpc_item(&args, &private).unwrap().to_string();
+            assert!(output.contains("struct CloseRequest"));
+            assert!(!output.contains("pub struct CloseRequest"));
+            let crate_visible: Item = parse_quote! {
+                pub(crate) fn close(runtime: &Rc<Runtime>, stream: RawHandle) {
+                    Ok(())
+                }
+            };
+            let output = rpc_item(&args, &crate_visible).unwrap().to_string();
+            assert!(output.contains("pub (crate) struct CloseRequest"));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let up = RpcArgs {
+            up: true,
+            ..RpcArgs::default()
+        };
+        assert_eq!(
+            error(
+                &up,
+                &parse_quote! {
+                    fn named(runtime: &Rc<Runtime>, name: &str) {
+                        Ok(())
+                    }
+                }
+            ),
+            "#[rpc] parameters are sent to the other side, so they cannot be references"
+        );
+        assert_eq!(
+            error(
+                &RpcArgs::default(),
+                &parse_quote! {
+                    impl Greet for dyn Greeter {
+                        #[rpc]
+                        fn greet<T>(self: &RcfRef<Self>, runtime: &Rc<Runtime>, name: T) {
+                            Ok(())
+                        }
+                    }
+                }
+            ),
+            "#[rpc] functions in trait impls cannot be generic"
+        );
+        assert_eq!(
+            error(
+                &RpcArgs::default(),
+                &parse_quote! {
+                    fn missing(name: String) {
+                        Ok(())
+                    }
+                }
+            ),
+            "#[rpc] functions take a `&Rc<Runtime>` parameter"
+        );
+        let handle = RpcArgs {
+            handle: Some(parse_quote!(stream)),
+            ..RpcArgs::default()
+        };
+        assert_eq!(
+            error(
+                &handle,
+                &parse_quote! {
+                    fn close(runtime: &Rc<Runtime>, handle: RawHandle) {
+                        Ok(())
+                    }
+                }
+            ),
+            "no such parameter"
+        );
+    }
 }
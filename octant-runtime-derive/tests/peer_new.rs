@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+
+//! Expands `PeerNewClient` and `PeerNewServer` against minimal stand-ins for the runtime crates,
+//! which the derives refer to by absolute path.
+
+extern crate self as octant_object;
+extern crate self as octant_runtime;
+extern crate self as octant_runtime_client;
+extern crate self as octant_runtime_server;
+
+use octant_runtime_derive::{PeerNewClient, PeerNewServer};
+
+use crate::peer::{AsNative, PeerFields};
+
+pub mod class {
+    pub trait ClassValue {
+        type Dyn: ?Sized;
+    }
+}
+
+pub mod peer {
+    pub trait AsNative {
+        type Native;
+        fn native(&self) -> &Self::Native;
+    }
+
+    #[derive(Clone)]
+    pub struct PeerFields;
+}
+
+pub trait PeerNew {
+    type Builder;
+    fn peer_new(builder: Self::Builder) -> Self;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Native(u32);
+
+/// The parent of every client peer below.
+pub struct ClientRoot;
+
+impl PeerNew for ClientRoot {
+    type Builder = Native;
+    fn peer_new(_: Native) -> Self {
+        ClientRoot
+    }
+}
+
+/// The parent of every server peer below.
+pub struct ServerRoot;
+
+impl PeerNew for ServerRoot {
+    type Builder = PeerFields;
+    fn peer_new(_: PeerFields) -> Self {
+        ServerRoot
+    }
+}
+
+/// Stands in for the `dyn` class of a fields type.
+pub struct Dyn<T>(T);
+
+impl<T> std::ops::Deref for Dyn<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+macro_rules! class_value {
+    ($fields:ident) => {
+        impl class::ClassValue for $fields {
+            type Dyn = Dyn<$fields>;
+        }
+    };
+}
+
+#[derive(PeerNewClient)]
+pub struct ClientTuple(ClientRoot, Native, u8);
+class_value!(ClientTuple);
+
+#[derive(PeerNewClient)]
+pub struct ClientRoles {
+    x: u32,
+    #[peer(native)]
+    n: Native,
+    #[peer(default = 7)]
+    y: u32,
+    #[peer(parent)]
+    p: ClientRoot,
+}
+class_value!(ClientRoles);
+
+#[derive(PeerNewClient)]
+pub enum ClientEnum {
+    Tuple(ClientRoot, Native),
+    #[peer(default)]
+    Named {
+        #[peer(native)]
+        native: Native,
+        parent: ClientRoot,
+        #[peer(default = 3)]
+        z: u8,
+    },
+}
+class_value!(ClientEnum);
+
+#[derive(PeerNewClient)]
+pub enum ClientTupleEnum {
+    Only(ClientRoot, Native, #[peer(default = 5)] u8),
+}
+class_value!(ClientTupleEnum);
+
+#[derive(PeerNewServer)]
+pub struct ServerTuple(ServerRoot, #[peer(default = String::from("a"))] String);
+
+#[derive(PeerNewServer)]
+pub enum ServerEnum {
+    #[peer(default)]
+    Named {
+        parent: ServerRoot,
+        count: u32,
+    },
+    Empty,
+}
+
+#[derive(PeerNewServer)]
+pub enum ServerTupleEnum {
+    Only(ServerRoot, #[peer(default = 9)] u64),
+}
+
+#[test]
+fn test_client_tuple() {
+    let peer = ClientTuple::peer_new(Native(1));
+    assert_eq!(peer.2, 0);
+    assert_eq!(Dyn(peer).native(), &Native(1));
+}
+
+#[test]
+fn test_client_roles() {
+    let peer = ClientRoles::peer_new(Native(2));
+    assert_eq!(peer.x, 0);
+    assert_eq!(peer.y, 7);
+    assert_eq!(Dyn(peer).native(), &Native(2));
+}
+
+#[test]
+fn test_client_enum() {
+    match ClientEnum::peer_new(Native(3)) {
+        ClientEnum::Named { z, .. } => assert_eq!(z, 3),
+        ClientEnum::Tuple(..) => panic!("expected the default variant"),
+    }
+    assert_eq!(Dyn(ClientEnum::peer_new(Native(3))).native(), &Native(3));
+    // The native object is found in whichever variant the peer currently holds.
+    let tuple = Dyn(ClientEnum::Tuple(ClientRoot, Native(4)));
+    assert_eq!(tuple.native(), &Native(4));
+    let ClientTupleEnum::Only(_, _, x) = ClientTupleEnum::peer_new(Native(5));
+    assert_eq!(x, 5);
+    assert_eq!(
+        Dyn(ClientTupleEnum::peer_new(Native(5))).native(),
+        &Native(5)
+    );
+}
+
+#[test]
+fn test_server_tuple() {
+    assert_eq!(ServerTuple::peer_new(PeerFields).1, "a");
+    let ServerTupleEnum::Only(_, x) = ServerTupleEnum::peer_new(PeerFields);
+    assert_eq!(x, 9);
+}
+
+#[test]
+fn test_server_enum() {
+    match ServerEnum::peer_new(PeerFields) {
+        ServerEnum::Named { count, .. } => assert_eq!(count, 0),
+        ServerEnum::Empty => panic!("expected the default variant"),
+    }
+}
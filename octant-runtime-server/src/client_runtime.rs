@@ -69,8 +69,11 @@ impl Runtime {
         )
         .map_err(|_| LookupError::DowncastFailed)?)
     }
-    pub fn delete(self: &Rc<Self>, handle: RawHandle) {
-        self.state.borrow_mut().handles.remove(&handle);
+    pub fn delete(self: &Rc<Self>, handles: Vec<RawHandle>) {
+        let ref mut state = *self.state.borrow_mut();
+        for handle in handles {
+            state.handles.remove(&handle);
+        }
     }
     // pub async fn run_batch(self: &Rc<Self>, messages: DownMessageList) -> OctantResult<()> {
     //     todo!();
@@ -5,8 +5,9 @@ use octant_runtime_derive::rpc;
 use crate::handle::RawHandle;
 use crate::runtime::Runtime;
 
-#[rpc]
-pub fn delete_rpc(runtime: &Rc<Runtime>, handle:RawHandle){
-    runtime.delete(handle);
+/// Deletes every handle dropped during one turn of the server's event loop.
+#[rpc(deletes = handles)]
+pub fn delete_batch(runtime: &Rc<Runtime>, handles: Vec<RawHandle>) {
+    runtime.delete(handles);
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,32 @@
+use std::rc::Rc;
+
+use marshal::{Deserialize, Serialize};
+use marshal_object::derive_variant;
+
+use octant_error::OctantError;
+#[cfg(side = "server")]
+use octant_error::OctantResult;
+
+use crate::{
+    handle::RawHandle,
+    proto::{BoxUpMessage, UpMessage},
+    runtime::Runtime,
+};
+
+/// Sent by the client when a [DownMessage](crate::proto::DownMessage) fails, instead of ending
+/// the session.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcFailed {
+    /// The peer the failed message was addressed to, if any.
+    pub handle: Option<RawHandle>,
+    pub error: OctantError,
+}
+
+derive_variant!(BoxUpMessage, RpcFailed);
+crate::register_message!(RpcFailed);
+impl UpMessage for RpcFailed {
+    #[cfg(side = "server")]
+    fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
+        runtime.rpc_failed(self.handle, self.error)
+    }
+}
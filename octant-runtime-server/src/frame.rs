@@ -0,0 +1,166 @@
+use octant_error::{octant_error, OctantError, OctantResult};
+
+const TAG_TEXT: u8 = 0;
+const TAG_BINARY: u8 = 1;
+/// The bytes [Frame::write_to] writes ahead of each frame.
+pub const FRAME_HEADER_LEN: usize = 5;
+
+/// One message of a transport, holding an encoded [DownMessageList](crate::proto::DownMessageList),
+/// [UpMessageList](crate::proto::UpMessageList) or [SessionHello](crate::proto::SessionHello).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Splits a byte stream written with [Frame::write_to] back into frames.
+#[derive(Default)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl Frame {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Frame::Text(x) => x.as_bytes(),
+            Frame::Binary(x) => x,
+        }
+    }
+    pub fn is_text(&self) -> bool {
+        matches!(self, Frame::Text(_))
+    }
+    /// Appends this frame with a length prefix, for transports that are not message-oriented.
+    pub fn write_to(&self, output: &mut Vec<u8>) {
+        let bytes = self.as_bytes();
+        output.push(if self.is_text() { TAG_TEXT } else { TAG_BINARY });
+        output.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        output.extend_from_slice(bytes);
+    }
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        FrameReader::default()
+    }
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+    pub fn next(&mut self) -> OctantResult<Option<Frame>> {
+        if self.buffer.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(self.buffer[1..FRAME_HEADER_LEN].try_into().unwrap()) as usize;
+        if self.buffer.len() < FRAME_HEADER_LEN + len {
+            return Ok(None);
+        }
+        let bytes = self.buffer[FRAME_HEADER_LEN..FRAME_HEADER_LEN + len].to_vec();
+        let tag = self.buffer[0];
+        self.buffer.drain(..FRAME_HEADER_LEN + len);
+        match tag {
+            TAG_TEXT => Ok(Some(Frame::Text(
+                String::from_utf8(bytes).map_err(OctantError::new)?,
+            ))),
+            TAG_BINARY => Ok(Some(Frame::Binary(bytes))),
+            _ => Err(octant_error!("unknown frame tag {}", tag)),
+        }
+    }
+    /// Whether the stream ended between frames.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use octant_error::OctantResult;
+
+    use crate::frame::{Frame, FrameReader};
+
+    fn encode(frames: &[Frame]) -> Vec<u8> {
+        let mut output = vec![];
+        for frame in frames {
+            frame.write_to(&mut output);
+        }
+        output
+    }
+
+    #[test]
+    fn test_whole() -> OctantResult<()> {
+        let frames = vec![
+            Frame::Text("hello".to_owned()),
+            Frame::Binary(vec![1, 2, 3]),
+            Frame::Text(String::new()),
+        ];
+        let mut reader = FrameReader::new();
+        reader.push(&encode(&frames));
+        for frame in frames {
+            assert_eq!(reader.next()?, Some(frame));
+        }
+        assert_eq!(reader.next()?, None);
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial() -> OctantResult<()> {
+        let frames = vec![
+            Frame::Text("hello".to_owned()),
+            Frame::Binary(vec![1, 2, 3]),
+        ];
+        let mut reader = FrameReader::new();
+        let mut read = vec![];
+        // One byte at a time, so that every length prefix and body is split.
+        for byte in encode(&frames) {
+            reader.push(&[byte]);
+            while let Some(frame) = reader.next()? {
+                read.push(frame);
+            }
+        }
+        assert_eq!(read, frames);
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_prefix() -> OctantResult<()> {
+        let bytes = encode(&[Frame::Binary(vec![7; 300])]);
+        let mut reader = FrameReader::new();
+        reader.push(&bytes[..3]);
+        assert_eq!(reader.next()?, None);
+        assert!(!reader.is_empty());
+        reader.push(&bytes[3..]);
+        assert_eq!(reader.next()?, Some(Frame::Binary(vec![7; 300])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized() -> OctantResult<()> {
+        // A length longer than the data waits for more rather than allocating it up front, and the
+        // caller reports the truncated frame once the stream ends.
+        let mut reader = FrameReader::new();
+        reader.push(&[1, 0xff, 0xff, 0xff, 0xff, 1, 2, 3]);
+        assert_eq!(reader.next()?, None);
+        assert!(!reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eof_mid_frame() -> OctantResult<()> {
+        let bytes = encode(&[Frame::Text("hello".to_owned())]);
+        let mut reader = FrameReader::new();
+        reader.push(&bytes[..bytes.len() - 1]);
+        assert_eq!(reader.next()?, None);
+        assert!(!reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut reader = FrameReader::new();
+        reader.push(&[9, 0, 0, 0, 0]);
+        assert!(reader.next().is_err());
+        let mut reader = FrameReader::new();
+        reader.push(&[0, 0, 0, 0, 1, 0xff]);
+        assert!(reader.next().is_err());
+    }
+}
@@ -23,6 +23,10 @@ pub trait FutureReturn: 'static + Debug {
     fn future_produce(self, runtime: &Rc<Runtime>, down: Self::Down) -> Self::Up;
     #[cfg(side = "server")]
     fn future_return(runtime: &Rc<Runtime>, retain: Self::Retain, up: Self::Up) -> Self;
+    /// Like [ImmediateReturn::immediate_mirror], for the peers the client creates when it produces
+    /// the value.
+    #[cfg(side = "server")]
+    fn future_mirror(runtime: &Rc<Runtime>, down: &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {}
 }
 
 #[cfg(side = "server")]
@@ -39,6 +43,9 @@ where
     fn future_return(runtime: &Rc<Runtime>, retain: Self::Retain, up: Self::Up) -> Self {
         retain
     }
+    fn future_mirror(runtime: &Rc<Runtime>, down: &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {
+        Self::immediate_mirror(runtime, down, peers)
+    }
 }
 
 #[cfg(side = "client")]
@@ -98,6 +105,11 @@ impl<T1: FutureReturn, T2: FutureReturn> FutureReturn for (T1, T2) {
             T2::future_return(runtime, r2, u2),
         )
     }
+    #[cfg(side = "server")]
+    fn future_mirror(runtime: &Rc<Runtime>, (d1, d2): &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {
+        T1::future_mirror(runtime, d1, peers);
+        T2::future_mirror(runtime, d2, peers);
+    }
 }
 
 impl<T: FutureReturn, E: FutureReturn> FutureReturn for Result<T, E> {
@@ -126,6 +138,11 @@ impl<T: FutureReturn, E: FutureReturn> FutureReturn for Result<T, E> {
             Err(e) => Err(E::future_return(runtime, er, e)),
         }
     }
+    #[cfg(side = "server")]
+    fn future_mirror(runtime: &Rc<Runtime>, (td, ed): &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {
+        T::future_mirror(runtime, td, peers);
+        E::future_mirror(runtime, ed, peers);
+    }
 }
 
 #[derive(Debug)]
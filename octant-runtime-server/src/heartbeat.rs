@@ -0,0 +1,12 @@
+use std::rc::Rc;
+
+use octant_runtime_derive::rpc;
+
+use crate::runtime::Runtime;
+
+/// Sent periodically by the server so that the client can tell a quiet session from a dead
+/// connection.
+#[rpc]
+pub fn heartbeat(runtime: &Rc<Runtime>) {
+    Ok(())
+}
@@ -25,6 +25,11 @@ pub trait ImmediateReturn: Sized {
     fn immediate_new(runtime: &Rc<Runtime>) -> (Self, Self::Down);
     #[cfg(side = "client")]
     fn immediate_return(self, runtime: &Rc<Runtime>, down: Self::Down);
+    /// Adds peers for the handles in `down` to `runtime`, which stands in for the client's, so that
+    /// later messages that refer to them can be decoded. The peers live as long as `peers` holds
+    /// them.
+    #[cfg(side = "server")]
+    fn immediate_mirror(runtime: &Rc<Runtime>, down: &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {}
 }
 
 #[cfg(side = "server")]
@@ -39,6 +44,13 @@ where
         let handle = (*peer).typed_handle();
         (peer, handle)
     }
+    fn immediate_mirror(runtime: &Rc<Runtime>, down: &Self::Down, peers: &mut Vec<Rcf<dyn Peer>>) {
+        let peer: Rcf<T> = runtime.add::<T::Fields>(T::Fields::peer_new(PeerFields::new(
+            runtime.clone(),
+            down.raw(),
+        )));
+        peers.push(peer);
+    }
 }
 
 #[cfg(side = "client")]
@@ -72,6 +84,15 @@ impl<T1: ImmediateReturn, T2: ImmediateReturn> ImmediateReturn for (T1, T2) {
         let (t2, t2d) = T2::immediate_new(runtime);
         ((t1, t2), (t1d, t2d))
     }
+    #[cfg(side = "server")]
+    fn immediate_mirror(
+        runtime: &Rc<Runtime>,
+        (d1, d2): &Self::Down,
+        peers: &mut Vec<Rcf<dyn Peer>>,
+    ) {
+        T1::immediate_mirror(runtime, d1, peers);
+        T2::immediate_mirror(runtime, d2, peers);
+    }
     #[cfg(side = "client")]
     fn immediate_return(self, runtime: &Rc<Runtime>, down: (T1::Down, T2::Down)) {
         self.0.immediate_return(runtime, down.0);
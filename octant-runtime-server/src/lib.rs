@@ -64,13 +64,19 @@ pub mod runtime;
 
 mod delete;
 pub mod error;
+pub mod frame;
 pub mod future_return;
+pub mod heartbeat;
 pub mod immediate_return;
 pub mod octant_future;
+pub mod octant_stream;
 #[cfg_attr(side = "client", path = "client_peer.rs")]
 #[cfg_attr(side = "server", path = "server_peer.rs")]
 pub mod peer;
 pub mod proto;
+#[cfg(all(side = "server", feature = "test-util"))]
+pub mod test_util;
+pub mod version;
 
 // pub fn deserialize_object_with<'de, T: ?Sized + Class, D: Deserializer<'de>>(
 //     ctx: &DeserializeContext,
@@ -8,6 +8,7 @@ use marshal::{
 };
 use marshal_object::derive_variant;
 use marshal_pointer::{Rcf, RcfRef};
+use octant_runtime_derive::rpc;
 #[cfg(side = "server")]
 use safe_once_async::detached::DetachedFuture;
 use std::{
@@ -17,18 +18,15 @@ use std::{
     marker::PhantomData,
     rc::Rc,
 };
+use std::{pin::Pin, task::Poll};
+#[cfg(side = "client")]
+use std::{future::poll_fn, pin::pin};
 #[cfg(side = "server")]
-use std::{
-    pin::Pin,
-    task::{Context, Poll},
-};
-#[cfg(side = "server")]
+use std::{task::Context, time::Duration};
 use tokio::sync::oneshot;
 
 #[cfg(side = "server")]
-use octant_error::octant_error;
-#[cfg(side = "server")]
-use octant_error::OctantResult;
+use octant_error::{octant_error, OctantError, OctantResult};
 use octant_object::{class, DebugClass};
 
 #[cfg(side = "server")]
@@ -36,12 +34,12 @@ use crate::immediate_return::AsTypedHandle;
 use crate::{
     deserialize_peer,
     future_return::FutureReturn,
-    handle::TypedHandle,
+    handle::{RawHandle, TypedHandle},
     immediate_return::ImmediateReturn,
     peer::{Peer, PeerFields},
     proto::{BoxUpMessage, UpMessage},
     runtime::Runtime,
-    serialize_peer,
+    serialize_peer, LookupError,
 };
 
 #[cfg(side = "server")]
@@ -52,6 +50,9 @@ pub struct AbstractOctantFutureFields {
     parent: PeerFields,
     #[cfg(side = "server")]
     sender: Sender,
+    /// Aborts the client's work when the server drops the [OctantFuture].
+    #[cfg(side = "client")]
+    cancel: RefCell<Option<oneshot::Sender<()>>>,
 }
 
 #[cfg(side = "server")]
@@ -68,6 +69,13 @@ impl Debug for Sender {
 #[class]
 pub trait AbstractOctantFuture: Peer {}
 
+#[cfg(side = "server")]
+impl dyn AbstractOctantFuture {
+    pub fn is_pending(&self) -> bool {
+        self.sender.0.borrow().is_some()
+    }
+}
+
 #[cfg(side = "server")]
 pub struct OctantFuture<T: FutureReturn> {
     parent: RcAbstractOctantFuture,
@@ -85,9 +93,11 @@ pub struct OctantFuture<T: FutureReturn> {
     phantom: PhantomData<T>,
 }
 
+/// The result of a future. The promise is identified by handle rather than by reference, since it
+/// may have been cancelled and deleted while the response was in flight.
 #[derive(Serialize, Deserialize)]
 pub struct FutureResponse {
-    promise: RcAbstractOctantFuture,
+    promise: RawHandle,
     value: Vec<u8>,
 }
 
@@ -100,11 +110,29 @@ impl Debug for FutureResponse {
     }
 }
 
+#[cfg(side = "server")]
+impl FutureResponse {
+    /// Builds a response on behalf of a client that is not backed by a client-side [Runtime].
+    pub fn new(promise: RawHandle, value: Vec<u8>) -> Self {
+        FutureResponse { promise, value }
+    }
+}
+
 derive_variant!(BoxUpMessage, FutureResponse);
+crate::register_message!(FutureResponse);
 impl UpMessage for FutureResponse {
     #[cfg(side = "server")]
     fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
-        self.promise
+        let promise = match runtime.lookup(TypedHandle::<dyn AbstractOctantFuture>::new(self.promise))
+        {
+            Ok(promise) => promise,
+            Err(LookupError::NotFound(_)) => {
+                tracing::debug!("Ignoring response to cancelled future {:?}", self.promise);
+                return Ok(());
+            }
+            Err(e) => return Err(OctantError::new(e)),
+        };
+        promise
             .sender
             .0
             .borrow_mut()
@@ -114,13 +142,19 @@ impl UpMessage for FutureResponse {
             .ok();
         Ok(())
     }
+    #[cfg(side = "server")]
+    fn handle(&self) -> Option<RawHandle> {
+        Some(self.promise)
+    }
 }
 
 #[cfg(side = "client")]
 impl<T: Debug + FutureReturn> OctantFuture<T> {
     pub fn spawn<F: 'static + Future<Output = T>>(runtime: &Rc<Runtime>, f: F) -> Self {
+        let (cancel_tx, mut cancel_rx) = oneshot::channel();
         let parent = Rcf::new(AbstractOctantFutureFields {
             parent: PeerFields::new(),
+            cancel: RefCell::new(Some(cancel_tx)),
         });
         let down = Rc::new(RefCell::new(None));
         wasm_bindgen_futures::spawn_local({
@@ -128,7 +162,17 @@ impl<T: Debug + FutureReturn> OctantFuture<T> {
             let down = down.clone();
             let runtime = runtime.clone();
             async move {
-                let result = f.await;
+                let mut f = pin!(f);
+                let result = poll_fn(|cx| {
+                    if Pin::new(&mut cancel_rx).poll(cx).is_ready() {
+                        return Poll::Ready(None);
+                    }
+                    f.as_mut().poll(cx).map(Some)
+                })
+                .await;
+                let Some(result) = result else {
+                    return;
+                };
                 let down = down.borrow_mut().take().unwrap();
                 let up = result.future_produce(&runtime, down);
                 let up = runtime
@@ -138,7 +182,7 @@ impl<T: Debug + FutureReturn> OctantFuture<T> {
                 runtime
                     .sink()
                     .send(Box::<FutureResponse>::new(FutureResponse {
-                        promise: parent,
+                        promise: parent.raw_handle(),
                         value: up,
                     }));
             }
@@ -159,16 +203,53 @@ impl<T: FutureReturn> Future for OctantFuture<T> {
             let mut ctx = OwnedContext::new();
             let runtime = (*self).parent.runtime();
             ctx.insert_const(runtime);
+            let bytes = up.len();
             let up = runtime.proto().deserialize::<T::Up>(&up, ctx.borrow())?;
             let retain = self.retain.take().unwrap();
             let result = T::future_return(self.parent.runtime(), retain, up);
-            log::info!("Future {:?} returned {:?}", (*self).parent.typed_handle().raw(), result);
+            tracing::debug!(
+                handle = ?(*self).parent.typed_handle().raw(),
+                bytes,
+                ?result,
+                "future returned"
+            );
             return Poll::Ready(Ok(result));
         }
         Poll::Pending
     }
 }
 
+#[cfg(side = "server")]
+impl<T: FutureReturn> OctantFuture<T> {
+    /// Fails with an error if the client does not respond within `duration`. The client's work is
+    /// cancelled when that happens.
+    pub async fn with_timeout(self, duration: Duration) -> OctantResult<T> {
+        tokio::time::timeout(duration, self)
+            .await
+            .map_err(|_| octant_error!("future timed out after {:?}", duration))?
+    }
+}
+
+/// Tells the client to abandon a future when the server drops it before it completes.
+#[cfg(side = "server")]
+impl<T: FutureReturn> Drop for OctantFuture<T> {
+    fn drop(&mut self) {
+        if self.retain.is_some() {
+            cancel_future(self.parent.runtime(), self.parent.typed_handle().raw());
+        }
+    }
+}
+
+#[rpc(handle = promise)]
+pub fn cancel_future(runtime: &Rc<Runtime>, promise: RawHandle) {
+    if let Ok(promise) = runtime.lookup(TypedHandle::<dyn AbstractOctantFuture>::new(promise)) {
+        if let Some(cancel) = promise.cancel.borrow_mut().take() {
+            cancel.send(()).ok();
+        }
+    }
+    Ok(())
+}
+
 impl<E: Encoder> SerializeRc<E> for dyn AbstractOctantFuture {
     fn serialize_rc<'w, 'en>(
         this: &RcfRef<Self>,
@@ -212,6 +293,21 @@ impl<T: FutureReturn> ImmediateReturn for OctantFuture<T> {
         )
     }
 
+    #[cfg(side = "server")]
+    fn immediate_mirror(
+        runtime: &Rc<Runtime>,
+        (handle, down): &Self::Down,
+        peers: &mut Vec<Rcf<dyn Peer>>,
+    ) {
+        peers.push(
+            runtime.add::<AbstractOctantFutureFields>(AbstractOctantFutureFields {
+                parent: PeerFields::new(runtime.clone(), handle.raw()),
+                sender: Sender(RefCell::new(None)),
+            }),
+        );
+        T::future_mirror(runtime, down, peers);
+    }
+
     #[cfg(side = "client")]
     fn immediate_return(self, runtime: &Rc<Runtime>, down: Self::Down) {
         *self.down.borrow_mut() = Some(down.1);
@@ -0,0 +1,376 @@
+//! A sequence of values produced by the client and consumed by the server.
+//!
+//! Items use the [FutureReturn] encoding, so each item needs a `Down` allocated by the server in
+//! advance. The server hands the client [STREAM_WINDOW] of these credits when the stream is
+//! created, and one more whenever it consumes an item, which also keeps a slow server from being
+//! flooded.
+
+use futures::Stream;
+use marshal::{
+    context::OwnedContext,
+    de::rc::DeserializeRc,
+    decode::{AnyDecoder, Decoder},
+    encode::{AnyEncoder, Encoder},
+    ser::rc::SerializeRc,
+};
+use marshal_pointer::{Rcf, RcfRef};
+use octant_runtime_derive::rpc;
+#[cfg(side = "server")]
+use std::task::Context;
+use std::{
+    cell::RefCell, collections::VecDeque, marker::PhantomData, pin::Pin, rc::Rc, task::Poll,
+};
+#[cfg(side = "client")]
+use std::{
+    fmt::Debug,
+    future::{poll_fn, Future},
+    pin::pin,
+};
+use tokio::sync::mpsc;
+#[cfg(side = "client")]
+use tokio::sync::oneshot;
+
+#[cfg(side = "server")]
+use octant_error::octant_error;
+use octant_error::{OctantError, OctantResult};
+use octant_object::{class, DebugClass};
+
+use crate::{
+    deserialize_peer,
+    future_return::FutureReturn,
+    handle::{RawHandle, TypedHandle},
+    immediate_return::ImmediateReturn,
+    peer::{Peer, PeerFields},
+    runtime::Runtime,
+    serialize_peer,
+};
+#[cfg(side = "server")]
+use crate::{immediate_return::AsTypedHandle, LookupError};
+
+/// How many items the client may send before the server consumes any.
+pub const STREAM_WINDOW: usize = 16;
+
+#[cfg(side = "server")]
+#[derive(Debug)]
+enum StreamEvent {
+    Item(Vec<u8>),
+    /// The client's stream ended, with an error if it failed.
+    End(Option<OctantError>),
+}
+
+#[derive(DebugClass)]
+pub struct AbstractOctantStreamFields {
+    parent: PeerFields,
+    #[cfg(side = "server")]
+    sender: RefCell<Option<mpsc::UnboundedSender<StreamEvent>>>,
+    /// Serialized `Down`s for items beyond the initial window.
+    #[cfg(side = "client")]
+    credits: RefCell<Option<mpsc::UnboundedSender<Vec<u8>>>>,
+    /// Stops the client's stream when the server closes it.
+    #[cfg(side = "client")]
+    cancel: RefCell<Option<oneshot::Sender<()>>>,
+}
+
+#[class]
+pub trait AbstractOctantStream: Peer {}
+
+#[cfg(side = "server")]
+impl dyn AbstractOctantStream {
+    pub fn is_open(&self) -> bool {
+        self.sender.borrow().is_some()
+    }
+}
+
+/// A stream of values produced by the client. Dropping it on the server, or calling
+/// [close](OctantStream::close), stops the client.
+#[cfg(side = "server")]
+pub struct OctantStream<T: FutureReturn> {
+    parent: RcAbstractOctantStream,
+    /// One entry for each credit the client holds, in the order it will use them.
+    retain: VecDeque<T::Retain>,
+    receiver: mpsc::UnboundedReceiver<StreamEvent>,
+    done: bool,
+    phantom: PhantomData<T>,
+}
+
+impl<T: FutureReturn> Unpin for OctantStream<T> {}
+
+#[cfg(side = "client")]
+pub struct OctantStream<T: FutureReturn> {
+    parent: RcAbstractOctantStream,
+    downs: Rc<RefCell<VecDeque<T::Down>>>,
+    phantom: PhantomData<T>,
+}
+
+#[rpc(up)]
+fn stream_item(runtime: &Rc<Runtime>, stream: RawHandle, value: Vec<u8>) {
+    send_event(runtime, stream, StreamEvent::Item(value))
+}
+
+#[rpc(up)]
+fn stream_end(runtime: &Rc<Runtime>, stream: RawHandle, error: Option<OctantError>) {
+    send_event(runtime, stream, StreamEvent::End(error))
+}
+
+/// Passes an event to the [OctantStream]. The stream is identified by handle rather than by
+/// reference, since it may have been closed and deleted while the event was in flight.
+#[cfg(side = "server")]
+fn send_event(runtime: &Rc<Runtime>, handle: RawHandle, event: StreamEvent) -> OctantResult<()> {
+    let stream = match runtime.lookup(TypedHandle::<dyn AbstractOctantStream>::new(handle)) {
+        Ok(stream) => stream,
+        Err(LookupError::NotFound(_)) => {
+            tracing::debug!("Ignoring event for closed stream {:?}", handle);
+            return Ok(());
+        }
+        Err(e) => return Err(OctantError::new(e)),
+    };
+    let ref mut sender = *stream.sender.borrow_mut();
+    let Some(tx) = sender else {
+        tracing::debug!("Ignoring event for closed stream {:?}", handle);
+        return Ok(());
+    };
+    let end = matches!(event, StreamEvent::End(_));
+    tx.send(event).ok();
+    if end {
+        *sender = None;
+    }
+    Ok(())
+}
+
+#[rpc(handle = stream)]
+fn stream_credit(runtime: &Rc<Runtime>, stream: RawHandle, down: Vec<u8>) {
+    if let Ok(stream) = runtime.lookup(TypedHandle::<dyn AbstractOctantStream>::new(stream)) {
+        if let Some(credits) = &*stream.credits.borrow() {
+            credits.send(down).ok();
+        }
+    }
+    Ok(())
+}
+
+#[rpc(handle = stream)]
+fn close_stream(runtime: &Rc<Runtime>, stream: RawHandle) {
+    if let Ok(stream) = runtime.lookup(TypedHandle::<dyn AbstractOctantStream>::new(stream)) {
+        stream.credits.borrow_mut().take();
+        if let Some(cancel) = stream.cancel.borrow_mut().take() {
+            cancel.send(()).ok();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(side = "client")]
+impl<T: Debug + FutureReturn> OctantStream<T> {
+    /// Sends the items of `stream` to the server until it ends, fails, or the server closes it.
+    pub fn spawn<S: 'static + Stream<Item = OctantResult<T>>>(
+        runtime: &Rc<Runtime>,
+        stream: S,
+    ) -> Self {
+        let (credit_tx, mut credit_rx) = mpsc::unbounded_channel();
+        let (cancel_tx, mut cancel_rx) = oneshot::channel();
+        let parent = Rcf::new(AbstractOctantStreamFields {
+            parent: PeerFields::new(),
+            credits: RefCell::new(Some(credit_tx)),
+            cancel: RefCell::new(Some(cancel_tx)),
+        });
+        let downs = Rc::new(RefCell::new(VecDeque::new()));
+        wasm_bindgen_futures::spawn_local({
+            let parent = parent.clone();
+            let downs = downs.clone();
+            let runtime = runtime.clone();
+            async move {
+                let mut stream = pin!(stream);
+                // Ok(false) if the server closed the stream.
+                let result: OctantResult<bool> = async {
+                    loop {
+                        let item = poll_fn(|cx| {
+                            if Pin::new(&mut cancel_rx).poll(cx).is_ready() {
+                                return Poll::Ready(None);
+                            }
+                            stream.as_mut().poll_next(cx).map(Some)
+                        })
+                        .await;
+                        let value = match item {
+                            None => return Ok(false),
+                            Some(None) => return Ok(true),
+                            Some(Some(value)) => value?,
+                        };
+                        let down = downs.borrow_mut().pop_front();
+                        let down = match down {
+                            Some(down) => down,
+                            None => {
+                                let Some(down) = credit_rx.recv().await else {
+                                    return Ok(false);
+                                };
+                                let mut ctx = OwnedContext::new();
+                                ctx.insert_const(&runtime);
+                                runtime
+                                    .proto()
+                                    .deserialize::<T::Down>(&down, ctx.borrow())?
+                            }
+                        };
+                        let up = value.future_produce(&runtime, down);
+                        let up = runtime
+                            .proto()
+                            .serialize(&up, OwnedContext::new().borrow())?;
+                        stream_item(&runtime, parent.raw_handle(), up);
+                    }
+                }
+                .await;
+                let error = match result {
+                    Ok(false) => return,
+                    Ok(true) => None,
+                    Err(error) => Some(error),
+                };
+                stream_end(&runtime, parent.raw_handle(), error);
+            }
+        });
+        OctantStream {
+            parent,
+            downs,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(side = "server")]
+impl<T: FutureReturn> OctantStream<T> {
+    /// Stops the client from sending more items. Items already in flight are discarded.
+    pub fn close(&mut self) {
+        if !self.done {
+            self.done = true;
+            self.retain.clear();
+            self.parent.sender.borrow_mut().take();
+            close_stream(self.parent.runtime(), self.parent.typed_handle().raw());
+        }
+    }
+    fn credit(&mut self) -> OctantResult<()> {
+        let runtime = self.parent.runtime();
+        let (retain, down) = T::future_new(runtime);
+        let down = runtime
+            .proto()
+            .serialize(&down, OwnedContext::new().borrow())?;
+        self.retain.push_back(retain);
+        stream_credit(runtime, self.parent.typed_handle().raw(), down);
+        Ok(())
+    }
+    fn item(&mut self, up: Vec<u8>) -> OctantResult<T> {
+        let runtime = self.parent.runtime().clone();
+        let mut ctx = OwnedContext::new();
+        ctx.insert_const(&runtime);
+        let up = runtime.proto().deserialize::<T::Up>(&up, ctx.borrow())?;
+        let retain = self
+            .retain
+            .pop_front()
+            .ok_or_else(|| octant_error!("stream item without credit"))?;
+        let result = T::future_return(&runtime, retain, up);
+        self.credit()?;
+        Ok(result)
+    }
+}
+
+#[cfg(side = "server")]
+impl<T: FutureReturn> Stream for OctantStream<T> {
+    type Item = OctantResult<T>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let event = match self.receiver.poll_recv(cx) {
+            Poll::Ready(event) => event,
+            Poll::Pending => return Poll::Pending,
+        };
+        let result = match event {
+            Some(StreamEvent::Item(up)) => self.item(up),
+            Some(StreamEvent::End(None)) | None => {
+                self.done = true;
+                return Poll::Ready(None);
+            }
+            Some(StreamEvent::End(Some(error))) => Err(error),
+        };
+        if result.is_err() {
+            self.close();
+        }
+        Poll::Ready(Some(result))
+    }
+}
+
+#[cfg(side = "server")]
+impl<T: FutureReturn> Drop for OctantStream<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<E: Encoder> SerializeRc<E> for dyn AbstractOctantStream {
+    fn serialize_rc<'w, 'en>(
+        this: &RcfRef<Self>,
+        e: AnyEncoder<'w, 'en, E>,
+        ctx: marshal::context::Context,
+    ) -> anyhow::Result<()> {
+        serialize_peer::<E, Self>(this, e, ctx)
+    }
+}
+
+impl<D: Decoder> DeserializeRc<D> for dyn AbstractOctantStream {
+    fn deserialize_rc<'p, 'de>(
+        d: AnyDecoder<'p, 'de, D>,
+        ctx: marshal::context::Context,
+    ) -> anyhow::Result<Rcf<Self>> {
+        deserialize_peer::<D, Self>(d, ctx)
+    }
+}
+
+impl<T: FutureReturn> ImmediateReturn for OctantStream<T> {
+    type Down = (TypedHandle<dyn AbstractOctantStream>, Vec<T::Down>);
+
+    #[cfg(side = "server")]
+    fn immediate_new(runtime: &Rc<Runtime>) -> (Self, Self::Down) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let peer: Rcf<dyn AbstractOctantStream> =
+            runtime.add::<AbstractOctantStreamFields>(AbstractOctantStreamFields {
+                parent: runtime.add_uninit(),
+                sender: RefCell::new(Some(tx)),
+            });
+        let handle = (*peer).typed_handle();
+        let mut retain = VecDeque::with_capacity(STREAM_WINDOW);
+        let mut downs = Vec::with_capacity(STREAM_WINDOW);
+        for _ in 0..STREAM_WINDOW {
+            let (r, d) = T::future_new(runtime);
+            retain.push_back(r);
+            downs.push(d);
+        }
+        (
+            OctantStream {
+                parent: peer,
+                retain,
+                receiver: rx,
+                done: false,
+                phantom: PhantomData,
+            },
+            (handle, downs),
+        )
+    }
+
+    #[cfg(side = "server")]
+    fn immediate_mirror(
+        runtime: &Rc<Runtime>,
+        (handle, downs): &Self::Down,
+        peers: &mut Vec<Rcf<dyn Peer>>,
+    ) {
+        peers.push(
+            runtime.add::<AbstractOctantStreamFields>(AbstractOctantStreamFields {
+                parent: PeerFields::new(runtime.clone(), handle.raw()),
+                sender: RefCell::new(None),
+            }),
+        );
+        for down in downs {
+            T::future_mirror(runtime, down, peers);
+        }
+    }
+
+    #[cfg(side = "client")]
+    fn immediate_return(self, runtime: &Rc<Runtime>, down: Self::Down) {
+        self.downs.borrow_mut().extend(down.1);
+        runtime.add(down.0, self.parent)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::runtime::Runtime;
+use crate::{frame::Frame, handle::RawHandle, runtime::Runtime};
 use marshal::{context::Context, Deserialize, Serialize};
 use marshal_fixed::{
     decode::full::{FixedDecoder, FixedDecoderBuilder},
@@ -20,15 +20,123 @@ use std::{
     rc::Rc,
     str::FromStr,
 };
+#[cfg(side = "server")]
+use std::{
+    any::{Any, TypeId},
+    hash::{Hash, Hasher},
+};
 use anyhow::Context as _;
+use flate2::read::DeflateDecoder;
+#[cfg(side = "server")]
+use flate2::write::DeflateEncoder;
+#[cfg(side = "server")]
+use crate::peer::Peer;
+#[cfg(side = "server")]
+use marshal::context::OwnedContext;
+#[cfg(side = "server")]
+use marshal_pointer::Rcf;
+#[cfg(side = "client")]
+use ruzstd::StreamingDecoder;
+use std::io::Read;
+#[cfg(side = "server")]
+use std::io::Write;
 
 #[cfg(side = "client")]
 pub trait DownMessage: Debug + RawAny + AsDiscriminant<BoxDownMessage> {
     fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()>;
+    /// The peer this message is addressed to, reported back to the server if [Self::run] fails.
+    fn handle(&self) -> Option<RawHandle> {
+        None
+    }
 }
 
+/// Messages are [Any] so that a client running on the server, such as the headless client, can
+/// read them by type.
 #[cfg(side = "server")]
-pub trait DownMessage: Debug + RawAny + AsDiscriminant<BoxDownMessage> {}
+pub trait DownMessage: Debug + Any + RawAny + AsDiscriminant<BoxDownMessage> {
+    /// The peer this message is addressed to.
+    fn handle(&self) -> Option<RawHandle> {
+        None
+    }
+    /// Set for last-writer-wins setters. Of the messages in a batch with equal keys, only the last
+    /// is sent.
+    fn coalesce_key(&self) -> Option<CoalesceKey<'_>> {
+        None
+    }
+    /// The handles this message deletes on the client.
+    fn deleted_handles(&self) -> &[RawHandle] {
+        &[]
+    }
+    /// The name of the message type, as recorded in traces.
+    fn message_name(&self) -> &'static str {
+        message_name::<Self>()
+    }
+    /// Adds the peers this message creates on the client to `runtime`, which stands in for the
+    /// client's. See
+    /// [ImmediateReturn::immediate_mirror](crate::immediate_return::ImmediateReturn::immediate_mirror).
+    fn mirror(&self, runtime: &Rc<Runtime>, peers: &mut Vec<Rcf<dyn Peer>>) {}
+}
+
+/// The state a coalescing message overwrites: a property of a peer, qualified by any arguments
+/// that select part of it, such as the name of a CSS property.
+#[cfg(side = "server")]
+#[derive(Debug)]
+pub struct CoalesceKey<'a> {
+    pub handle: RawHandle,
+    pub property: &'static str,
+    pub arguments: Vec<&'a dyn CoalesceArgument>,
+}
+
+/// A parameter of a message that is part of its [CoalesceKey]. Arguments are equal if they have the
+/// same type and value.
+#[cfg(side = "server")]
+pub trait CoalesceArgument: Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_argument(&self, other: &dyn CoalesceArgument) -> bool;
+    fn hash_argument(&self, state: &mut dyn Hasher);
+}
+
+#[cfg(side = "server")]
+impl<T: 'static + Debug + Eq + Hash> CoalesceArgument for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn eq_argument(&self, other: &dyn CoalesceArgument) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+    fn hash_argument(&self, mut state: &mut dyn Hasher) {
+        TypeId::of::<T>().hash(&mut state);
+        self.hash(&mut state);
+    }
+}
+
+#[cfg(side = "server")]
+impl<'a> PartialEq for CoalesceKey<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.handle == other.handle
+            && self.property == other.property
+            && self.arguments.len() == other.arguments.len()
+            && self
+                .arguments
+                .iter()
+                .zip(other.arguments.iter())
+                .all(|(x, y)| x.eq_argument(*y))
+    }
+}
+
+#[cfg(side = "server")]
+impl<'a> Eq for CoalesceKey<'a> {}
+
+#[cfg(side = "server")]
+impl<'a> Hash for CoalesceKey<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state);
+        self.property.hash(state);
+        for argument in &self.arguments {
+            argument.hash_argument(state);
+        }
+    }
+}
 
 #[cfg(side = "client")]
 pub trait UpMessage: Debug + RawAny + AsDiscriminant<BoxUpMessage> {}
@@ -36,6 +144,22 @@ pub trait UpMessage: Debug + RawAny + AsDiscriminant<BoxUpMessage> {}
 #[cfg(side = "server")]
 pub trait UpMessage: Debug + RawAny + AsDiscriminant<BoxUpMessage> {
     fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()>;
+    /// The peer this message was sent by.
+    fn handle(&self) -> Option<RawHandle> {
+        None
+    }
+    /// The name of the message type, as recorded in traces.
+    fn message_name(&self) -> &'static str {
+        message_name::<Self>()
+    }
+}
+
+/// The last segment of the path of `T`, without generic arguments.
+#[cfg(side = "server")]
+fn message_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
 }
 
 pub struct BoxDownMessage;
@@ -50,9 +174,30 @@ derive_deserialize_provider!(BoxUpMessage, JsonDecoder, FixedDecoder);
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UpMessageList {
+    /// The number of [DownMessageList]s the client has processed in this session.
+    pub ack: u64,
     pub commands: Vec<Vec<u8>>,
 }
 
+/// The first frame the client sends on every socket, before any [UpMessageList].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClientHello {
+    /// The client's [protocol_version](crate::version::protocol_version).
+    pub protocol: u64,
+}
+
+/// The first frame the server sends on every socket, before any [DownMessageList].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionHello {
+    /// Presented by the client to resume the session after a reconnect.
+    pub token: String,
+    /// The number of [UpMessageList]s the server has processed, or `None` if the session could not
+    /// be resumed or the client is out of date. Either way the client reloads.
+    pub received: Option<u64>,
+    /// How long the client should wait for a frame before treating the connection as lost.
+    pub idle_timeout_millis: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DownMessageList {
     pub commands: Vec<Vec<u8>>,
@@ -64,6 +209,25 @@ pub enum Proto {
     Fixed,
 }
 
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Hash, Copy, Clone)]
+pub enum Compression {
+    None,
+    Deflate,
+    Zstd,
+}
+
+/// How frames are encoded, such as `json` or `fixed+zstd`.
+///
+/// Compression applies only to frames sent to the client, which carry most of the traffic. Frames
+/// sent by the client are always encoded with the [Proto] alone, because the zstd encoder does not
+/// build for wasm. Each frame is compressed independently, so that frames replayed after a
+/// reconnect can still be decoded.
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Hash, Copy, Clone)]
+pub struct Wire {
+    pub proto: Proto,
+    pub compression: Compression,
+}
+
 impl FromStr for Proto {
     type Err = OctantError;
 
@@ -101,6 +265,96 @@ impl Proto {
     }
 }
 
+impl FromStr for Wire {
+    type Err = OctantError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (proto, compression) = match s.split_once('+') {
+            None => (s, Compression::None),
+            Some((proto, "deflate")) => (proto, Compression::Deflate),
+            Some((proto, "zstd")) => (proto, Compression::Zstd),
+            Some(_) => return Err(octant_error!("unexpected compression")),
+        };
+        Ok(Wire {
+            proto: proto.parse()?,
+            compression,
+        })
+    }
+}
+
+impl From<Proto> for Wire {
+    fn from(proto: Proto) -> Self {
+        Wire {
+            proto,
+            compression: Compression::None,
+        }
+    }
+}
+
+impl Wire {
+    /// Every wire, in the order a client offers them.
+    pub fn all() -> Vec<Wire> {
+        let mut all = vec![];
+        for proto in [Proto::Fixed, Proto::Json] {
+            for compression in [Compression::Zstd, Compression::Deflate, Compression::None] {
+                all.push(Wire { proto, compression });
+            }
+        }
+        all
+    }
+    /// Picks the first wire in `preference` that appears in `offered`, a comma-separated list in
+    /// the format of a `Sec-WebSocket-Protocol` header. Wires that cannot be parsed are ignored.
+    pub fn negotiate(offered: &str, preference: &[Wire]) -> Option<Wire> {
+        let offered: Vec<Wire> = offered
+            .split(',')
+            .filter_map(|x| x.trim().parse().ok())
+            .collect();
+        preference.iter().find(|x| offered.contains(x)).copied()
+    }
+    #[cfg(side = "server")]
+    pub fn encode<T: SerializeJson + SerializeFixed>(&self, value: &T) -> OctantResult<Frame> {
+        let mut ctx = OwnedContext::new();
+        let bytes = self.proto.serialize(value, ctx.borrow())?;
+        Ok(match (self.compression, self.proto) {
+            (Compression::None, Proto::Json) => {
+                Frame::Text(String::from_utf8(bytes).map_err(OctantError::new)?)
+            }
+            (Compression::None, Proto::Fixed) => Frame::Binary(bytes),
+            (Compression::Deflate, _) => {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(&bytes)?;
+                Frame::Binary(encoder.finish()?)
+            }
+            (Compression::Zstd, _) => Frame::Binary(zstd::encode_all(&*bytes, 0)?),
+        })
+    }
+    /// Decodes a frame written by [Self::encode]. The server uses this to inspect its own frames.
+    pub fn decode<T: DeserializeJson + DeserializeFixed>(
+        &self,
+        frame: &Frame,
+        ctx: Context,
+    ) -> OctantResult<T> {
+        let mut bytes = vec![];
+        let bytes = match self.compression {
+            Compression::None => frame.as_bytes(),
+            Compression::Deflate => {
+                DeflateDecoder::new(frame.as_bytes()).read_to_end(&mut bytes)?;
+                &bytes
+            }
+            Compression::Zstd => {
+                #[cfg(side = "client")]
+                StreamingDecoder::new(frame.as_bytes())
+                    .map_err(OctantError::new)?
+                    .read_to_end(&mut bytes)?;
+                #[cfg(side = "server")]
+                zstd::stream::copy_decode(frame.as_bytes(), &mut bytes)?;
+                &bytes
+            }
+        };
+        Ok(self.proto.deserialize(bytes, ctx)?)
+    }
+}
+
 impl Display for Proto {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -109,3 +363,69 @@ impl Display for Proto {
         }
     }
 }
+
+impl Display for Wire {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.compression {
+            Compression::None => write!(f, "{}", self.proto),
+            Compression::Deflate => write!(f, "{}+deflate", self.proto),
+            Compression::Zstd => write!(f, "{}+zstd", self.proto),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(side = "server")]
+    use marshal::context::OwnedContext;
+
+    use octant_error::OctantResult;
+
+    #[cfg(side = "server")]
+    use crate::{frame::Frame, proto::SessionHello};
+    use crate::proto::{Compression, Proto, Wire};
+
+    #[test]
+    fn test_wire_names() -> OctantResult<()> {
+        for wire in Wire::all() {
+            assert_eq!(wire.to_string().parse::<Wire>()?, wire);
+        }
+        assert_eq!(
+            Wire::negotiate("json, fixed+zstd, bogus", &Wire::all()),
+            Some(Wire {
+                proto: Proto::Fixed,
+                compression: Compression::Zstd
+            })
+        );
+        Ok(())
+    }
+
+    #[cfg(side = "server")]
+    #[test]
+    fn test_round_trip() -> OctantResult<()> {
+        for wire in Wire::all() {
+            let hello = SessionHello {
+                token: "a".repeat(1000),
+                received: Some(42),
+                idle_timeout_millis: None,
+            };
+            let frame = wire.encode(&hello)?;
+            let uncompressed = wire.proto.serialize(&hello, OwnedContext::new().borrow())?;
+            match (wire.proto, wire.compression) {
+                (Proto::Json, Compression::None) => assert!(frame.is_text()),
+                (Proto::Fixed, Compression::None) => {
+                    assert_eq!(frame, Frame::Binary(uncompressed))
+                }
+                _ => {
+                    assert!(!frame.is_text());
+                    assert!(frame.as_bytes().len() < uncompressed.len());
+                }
+            }
+            let decoded: SessionHello = wire.decode(&frame, OwnedContext::new().borrow())?;
+            assert_eq!(decoded.token, hello.token);
+            assert_eq!(decoded.received, Some(42));
+            assert_eq!(decoded.idle_timeout_millis, None);
+        }
+        Ok(())
+    }
+}
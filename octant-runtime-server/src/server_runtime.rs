@@ -1,20 +1,27 @@
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
     fmt::{Debug, Formatter},
+    mem,
     rc::Rc,
 };
 
 use atomic_refcell::AtomicRefCell;
 use marshal::context::OwnedContext;
 use marshal_pointer::{Rcf, RcfWeak};
-use octant_error::OctantResult;
+use octant_error::{OctantError, OctantResult};
 use octant_executor::event_loop::EventSpawn;
-use octant_object::{cast::downcast_object, class::Class};
+use octant_object::{
+    cast::{downcast_object, CastValue},
+    class::Class,
+};
 use tokio::sync::mpsc::UnboundedSender;
 use weak_table::WeakValueHashMap;
 
 use crate::{
-    delete::delete_rpc,
+    delete::delete_batch,
     handle::{RawHandle, TypedHandle},
+    octant_future::AbstractOctantFuture,
     peer::{Peer, PeerFields},
     proto::{DownMessage, Proto, UpMessage, UpMessageList},
     LookupError,
@@ -23,13 +30,19 @@ use crate::{
 struct State {
     next_handle: u64,
     handles: WeakValueHashMap<RawHandle, RcfWeak<dyn Peer>>,
+    /// Handles dropped since the last [delete_batch].
+    deletes: Vec<RawHandle>,
 }
 
+/// Called with the peer a failed client-side RPC was addressed to, if it still exists.
+pub type RpcErrorHandler = Rc<dyn Fn(Option<Rcf<dyn Peer>>, OctantError) -> OctantResult<()>>;
+
 pub struct Runtime {
     proto: Proto,
     state: AtomicRefCell<State>,
     spawn: Rc<EventSpawn>,
     sink: UnboundedSender<Box<dyn DownMessage>>,
+    rpc_error_handler: RefCell<Option<RpcErrorHandler>>,
 }
 
 impl Debug for Runtime {
@@ -49,9 +62,11 @@ impl Runtime {
             state: AtomicRefCell::new(State {
                 next_handle: 0,
                 handles: WeakValueHashMap::new(),
+                deletes: vec![],
             }),
             spawn,
             sink,
+            rpc_error_handler: RefCell::new(None),
         }
     }
     pub fn send(&self, command: Box<dyn DownMessage>) {
@@ -78,17 +93,52 @@ impl Runtime {
         }
         PeerFields::new(self.clone(), handle)
     }
+    /// Queues `handle` for deletion on the client. Deletes are sent together in a microtask, so
+    /// dropping many peers in one turn of the event loop sends a single message.
     pub fn delete(self: &Rc<Self>, handle: RawHandle) {
-        delete_rpc(self, handle);
+        let first = {
+            let ref mut state = *self.state.borrow_mut();
+            state.deletes.push(handle);
+            state.deletes.len() == 1
+        };
+        if first {
+            self.spawn.spawn({
+                let this = self.clone();
+                async move {
+                    this.flush_deletes();
+                    Ok(())
+                }
+            });
+        }
+    }
+    fn flush_deletes(self: &Rc<Self>) {
+        let handles = mem::take(&mut self.state.borrow_mut().deletes);
+        if !handles.is_empty() {
+            delete_batch(self, handles);
+        }
     }
     pub fn run_batch(self: &Rc<Self>, batch: UpMessageList) -> OctantResult<()> {
+        let _span = tracing::debug_span!(
+            "batch",
+            commands = batch.commands.len(),
+            bytes = batch.commands.iter().map(|c| c.len()).sum::<usize>()
+        )
+        .entered();
         let mut ctx = OwnedContext::new();
         ctx.insert_const(self);
-        for message in batch.commands {
+        for bytes in batch.commands {
             let message = self
                 .proto
-                .deserialize::<Box<dyn UpMessage>>(&message, ctx.borrow())?;
-            log::info!("UpMessage: {:#?}", message);
+                .deserialize::<Box<dyn UpMessage>>(&bytes, ctx.borrow())?;
+            let _span = tracing::debug_span!(
+                "rpc",
+                direction = "up",
+                message = message.message_name(),
+                handle = ?message.handle(),
+                bytes = bytes.len()
+            )
+            .entered();
+            tracing::trace!(?message);
             self.run_message(message)?;
         }
         Ok(())
@@ -112,4 +162,44 @@ impl Runtime {
     pub fn proto(&self) -> Proto {
         self.proto
     }
+    /// The number of handles in use. This may include peers that were dropped recently.
+    pub fn handle_count(&self) -> usize {
+        self.state.borrow().handles.len()
+    }
+    /// The number of live peers of each class.
+    pub fn peer_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for peer in self.state.borrow().handles.values() {
+            *counts.entry(peer.class_name()).or_default() += 1;
+        }
+        counts
+    }
+    /// The number of [OctantFuture](crate::octant_future::OctantFuture)s awaiting the client.
+    pub fn pending_futures(&self) -> usize {
+        self.state
+            .borrow()
+            .handles
+            .values()
+            .filter_map(|peer| downcast_object::<_, Rcf<dyn AbstractOctantFuture>>(peer).ok())
+            .filter(|future| future.is_pending())
+            .count()
+    }
+    pub fn set_rpc_error_handler(&self, handler: RpcErrorHandler) {
+        *self.rpc_error_handler.borrow_mut() = Some(handler);
+    }
+    /// Reports an RPC that failed on the client. Without a handler the error is only logged.
+    pub fn rpc_failed(
+        self: &Rc<Self>,
+        handle: Option<RawHandle>,
+        error: OctantError,
+    ) -> OctantResult<()> {
+        let peer = handle.and_then(|handle| self.lookup(TypedHandle::<dyn Peer>::new(handle)).ok());
+        let handler = self.rpc_error_handler.borrow().clone();
+        if let Some(handler) = handler {
+            handler(peer, error)
+        } else {
+            tracing::error!("RPC failed on client for {:?}: {:?}", peer, error);
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,25 @@
+//! Fixtures for running a server [Runtime] without a client.
+
+use std::{rc::Rc, task::Poll};
+
+use octant_executor::event_loop::EventPool;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::{
+    proto::{DownMessage, Proto},
+    runtime::Runtime,
+};
+
+/// A runtime whose messages are left in the returned receiver. Dropped peers spawn their deletes
+/// on the returned pool, which runs them when stepped and discards them when dropped.
+pub fn detached_runtime(
+    proto: Proto,
+) -> (
+    EventPool,
+    Rc<Runtime>,
+    UnboundedReceiver<Box<dyn DownMessage>>,
+) {
+    let (spawn, pool) = EventPool::new(|_| Poll::Ready(Ok(())));
+    let (tx, rx) = unbounded_channel();
+    (pool, Rc::new(Runtime::new(proto, tx, spawn)), rx)
+}
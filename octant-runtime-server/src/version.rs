@@ -0,0 +1,158 @@
+//! Identifies the set of messages a build of the client or server understands, so that a stale
+//! client can be detected before it fails to decode anything.
+
+use std::sync::OnceLock;
+
+use catalog::{Builder, BuilderFrom, Registry};
+
+/// A message type registered with [register_message](crate::register_message).
+pub struct MessageVariant {
+    /// The module path and the type as written, with the crate renamed to its shared name so that
+    /// both sides agree. Unlike [std::any::type_name], this does not depend on the compiler.
+    name: String,
+    version: &'static str,
+}
+
+pub struct ProtocolRegistry {
+    variants: Vec<String>,
+}
+
+pub static PROTOCOL_REGISTRY: Registry<ProtocolRegistry> = Registry::new();
+
+impl MessageVariant {
+    pub fn new(
+        module_path: &str,
+        type_tokens: &str,
+        crate_name: &str,
+        shared_name: Option<&str>,
+        version: &'static str,
+    ) -> Self {
+        let module_path = match (shared_name, module_path.strip_prefix(crate_name)) {
+            (Some(shared_name), Some(rest)) => format!("{}{}", shared_name.replace('-', "_"), rest),
+            _ => module_path.to_owned(),
+        };
+        let type_tokens: String = type_tokens.split_whitespace().collect();
+        MessageVariant {
+            name: format!("{}::{}", module_path, type_tokens),
+            version,
+        }
+    }
+    pub fn key(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+impl Builder for ProtocolRegistry {
+    type Output = ProtocolRegistry;
+
+    fn new() -> Self {
+        ProtocolRegistry { variants: vec![] }
+    }
+
+    fn build(mut self) -> Self::Output {
+        self.variants.sort();
+        self.variants.dedup();
+        self
+    }
+}
+
+impl BuilderFrom<&'static MessageVariant> for ProtocolRegistry {
+    fn insert(&mut self, element: &'static MessageVariant) {
+        self.variants.push(element.key());
+    }
+}
+
+impl ProtocolRegistry {
+    pub fn variants(&self) -> &[String] {
+        &self.variants
+    }
+}
+
+/// A hash of every registered message and the version of the crate that defines it.
+pub fn protocol_version() -> u64 {
+    static VERSION: OnceLock<u64> = OnceLock::new();
+    *VERSION.get_or_init(|| hash_variants(PROTOCOL_REGISTRY.variants()))
+}
+
+/// Hashes message keys regardless of the order they were registered in. This uses FNV-1a rather
+/// than [std::hash] so that wasm and native builds agree.
+pub fn hash_variants<S: AsRef<str>>(variants: &[S]) -> u64 {
+    let mut variants: Vec<&str> = variants.iter().map(|x| x.as_ref()).collect();
+    variants.sort();
+    variants.dedup();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for variant in variants {
+        for byte in variant.bytes().chain([b'\n']) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Registers a message type with [PROTOCOL_REGISTRY]. Use alongside
+/// [derive_variant](marshal_object::derive_variant) for every [UpMessage](crate::proto::UpMessage)
+/// and [DownMessage](crate::proto::DownMessage).
+#[macro_export]
+macro_rules! register_message {
+    ($type:ty) => {
+        const _: () = {
+            #[$crate::reexports::catalog::register($crate::version::PROTOCOL_REGISTRY, lazy = true, crate = $crate::reexports::catalog)]
+            static VARIANT: $crate::version::MessageVariant = $crate::version::MessageVariant::new(
+                module_path!(),
+                stringify!($type),
+                env!("CARGO_CRATE_NAME"),
+                option_env!("MARSHAL_OBJECT_RENAME_CRATE"),
+                env!("CARGO_PKG_VERSION"),
+            );
+        };
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::version::{hash_variants, MessageVariant};
+
+    #[test]
+    fn test_order_independent() {
+        let a = "octant_runtime::error::RpcFailed@0.1.0";
+        let b = "octant_runtime::octant_future::FutureResponse@0.1.0";
+        assert_eq!(hash_variants(&[a, b]), hash_variants(&[b, a]));
+        assert_eq!(hash_variants(&[a, b]), hash_variants(&[b, a, b]));
+        assert_ne!(hash_variants(&[a, b]), hash_variants(&[a]));
+    }
+
+    #[test]
+    fn test_sides_agree() {
+        let server = MessageVariant::new(
+            "octant_web_sys_server::node",
+            "SetNodeValueRequest",
+            "octant_web_sys_server",
+            Some("octant-web-sys"),
+            "0.1.0",
+        );
+        let client = MessageVariant::new(
+            "octant_web_sys_client::node",
+            "SetNodeValueRequest",
+            "octant_web_sys_client",
+            Some("octant-web-sys"),
+            "0.1.0",
+        );
+        assert_eq!(
+            server.key(),
+            "octant_web_sys::node::SetNodeValueRequest@0.1.0"
+        );
+        assert_eq!(server.key(), client.key());
+        let generic = MessageVariant::new(
+            "octant_web_sys_client::node",
+            "FooRequest < u32 , String >",
+            "octant_web_sys_client",
+            Some("octant-web-sys"),
+            "0.1.0",
+        );
+        assert_eq!(
+            generic.key(),
+            "octant_web_sys::node::FooRequest<u32,String>@0.1.0"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Reports the bytes sent to the client for the scoreboard's initial render, for each [Wire].
+//!
+//! Run with `cargo bench -p octant-scoreboard --bench wire_bytes`.
+
+#![deny(unused_must_use)]
+#![feature(trait_upcasting)]
+#![allow(unused_variables)]
+#![allow(dead_code)]
+#![feature(arbitrary_self_types)]
+#![feature(never_type)]
+
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    future::{pending, ready},
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::SinkExt;
+use parking_lot::Mutex;
+use tokio::{sync::mpsc, task::LocalSet, time::timeout};
+use url::Url;
+
+use octant_account::SessionTable;
+use octant_cookies::CookieRouter;
+use octant_database::{database::Database, file::DatabaseFile};
+use octant_executor::event_loop::EventPool;
+use octant_runtime_server::{
+    proto::{DownMessageList, Wire},
+    reexports::octant_error::{OctantError, OctantResult},
+    runtime::Runtime,
+};
+use octant_server::{session::Session, sink::BufferedDownMessageSink, OctantServer};
+use octant_web_sys_server::global::Global;
+
+use crate::app::ScoreApplication;
+
+#[path = "../src/app.rs"]
+mod app;
+#[path = "../src/puzzle.rs"]
+mod puzzle;
+
+const WIRES: &[&str] = &[
+    "json",
+    "json+deflate",
+    "json+zstd",
+    "fixed",
+    "fixed+deflate",
+    "fixed+zstd",
+];
+
+/// How long to let the session run before counting, since nothing signals the end of a render.
+const RENDER_TIME: Duration = Duration::from_millis(200);
+
+#[derive(Copy, Clone, Default)]
+struct WireStats {
+    frames: usize,
+    bytes: usize,
+}
+
+async fn initial_render(app: Arc<ScoreApplication>, wire: Wire) -> OctantResult<WireStats> {
+    let stats = Rc::new(Cell::new(WireStats::default()));
+    let counter = futures::sink::drain()
+        .sink_map_err(|e: Infallible| -> OctantError { match e {} })
+        .with({
+            let stats = stats.clone();
+            move |list: DownMessageList| {
+                ready(wire.encode(&list).map(|frame| {
+                    let WireStats { frames, bytes } = stats.get();
+                    stats.set(WireStats {
+                        frames: frames + 1,
+                        bytes: bytes + frame.as_bytes().len(),
+                    });
+                }))
+            }
+        });
+    let (tx_inner, rx_inner) = mpsc::unbounded_channel();
+    let mut sink = BufferedDownMessageSink::new(wire.proto, rx_inner, Box::pin(counter));
+    let (spawn, mut pool) = EventPool::new(move |cx| sink.poll_flush(cx));
+    let runtime = Rc::new(Runtime::new(wire.proto, tx_inner, spawn.clone()));
+    let global = Global::new(runtime);
+    let session = Rc::new(Session::new(global));
+    let url = Url::parse("http://localhost:8080/puzzle1")?;
+    spawn.spawn(async move {
+        let _component = OctantServer::start_application(app, session, &url)?;
+        pending::<!>().await;
+        Ok(())
+    });
+    if let Ok(result) = timeout(RENDER_TIME, pool.run()).await {
+        result?;
+    }
+    Ok(stats.get())
+}
+
+async fn main_impl() -> OctantResult<()> {
+    let db_path = Path::new("../target/wire_bytes_db");
+    std::fs::create_dir_all(db_path)?;
+    let (_db_writer, db) = DatabaseFile::<Database>::new(db_path).await?;
+    let app = Arc::new(ScoreApplication {
+        db,
+        cookies: CookieRouter::new(),
+        sessions: SessionTable::new(),
+        guesses: Mutex::new(vec![]),
+    });
+    let mut baseline = None;
+    println!("{:<16}{:>8}{:>10}{:>8}", "wire", "frames", "bytes", "ratio");
+    for wire in WIRES {
+        let stats = initial_render(app.clone(), wire.parse()?).await?;
+        let baseline = *baseline.get_or_insert(stats.bytes);
+        println!(
+            "{:<16}{:>8}{:>10}{:>8.3}",
+            wire,
+            stats.frames,
+            stats.bytes,
+            stats.bytes as f64 / baseline as f64
+        );
+    }
+    Ok(())
+}
+
+fn main() -> OctantResult<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    LocalSet::new().block_on(&runtime, main_impl())
+}
@@ -21,9 +21,9 @@ mod puzzle;
 
 #[tokio::main]
 async fn main() -> OctantResult<()> {
-    simple_logger::SimpleLogger::new().env().init().unwrap();
-    register_panic_handler();
     let options = OctantServerOptions::from_command_line();
+    options.init_tracing()?;
+    register_panic_handler();
     let mut server = OctantServer::new(options).await?;
     let cookies = CookieRouter::new();
     cookies.register(&mut server);
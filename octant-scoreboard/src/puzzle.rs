@@ -2,6 +2,7 @@ use marshal_pointer::{EmptyRcf, Rcf, RcfRef};
 use octant_components::{Component, ComponentBuilder};
 use octant_database::database::ArcDatabase;
 use octant_error::OctantResult;
+use octant_executor::event_loop::AbortOnDrop;
 use octant_server::session::Session;
 use octant_web_sys_server::{
     attributes::input_type::InputType, html_div_element::RcHtmlDivElement,
@@ -19,6 +20,7 @@ pub struct PuzzleComponent {
     session: Rc<Session>,
     div: RcHtmlDivElement,
     guess: RcHtmlInputElement,
+    fetch: AbortOnDrop<()>,
 }
 
 impl PuzzleComponentBuilder {
@@ -64,7 +66,7 @@ impl ComponentBuilder for PuzzleComponentBuilder {
             content_div = d.create_div_element();
             content_div.clone()
         });
-        self.session.global().runtime().spawner().spawn({
+        let fetch = self.session.global().runtime().spawner().spawn({
             let this = this.downgrade();
             async move {
                 if let Some(this) = this.upgrade() {
@@ -86,6 +88,7 @@ impl ComponentBuilder for PuzzleComponentBuilder {
             session: self.session.clone(),
             div,
             guess,
+            fetch: fetch.abort_on_drop(),
         }))
     }
 }
@@ -0,0 +1,677 @@
+//! A native client for driving an [OctantApplication] from tests, without a browser.
+//!
+//! The client-side RPC implementations call into `web_sys`, so the headless client decodes each
+//! [DownMessage] into its server-side type and applies it to a simulated DOM instead. A server
+//! [Runtime] stands in for the client's, holding a peer for each object the client would have
+//! created, so that messages addressed to those objects can be decoded. This works over any
+//! [Wire]. Messages sent back to the server use the real [UpMessage] types.
+
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+    rc::Rc,
+    sync::Arc,
+};
+
+use futures::{FutureExt, SinkExt, StreamExt};
+use marshal::context::OwnedContext;
+use marshal_pointer::Rcf;
+use tokio::sync::watch;
+use url::Url;
+
+use octant_error::{octant_error, OctantResult};
+use octant_executor::event_loop::EventPool;
+use octant_runtime_server::{
+    frame::Frame,
+    handle::{RawHandle, TypedHandle},
+    octant_future::{AbstractOctantFuture, CancelFutureRequest, FutureResponse},
+    peer::Peer,
+    proto::{DownMessage, DownMessageList, SessionHello, UpMessage, UpMessageList, Wire},
+    runtime::Runtime,
+    test_util::detached_runtime,
+    OctantSerialize,
+};
+use octant_web_sys_server::{
+    css_style_declaration::SetPropertyImplRequest,
+    document::{
+        BodyImplRequest, CreateAnchorElementRequest, CreateBrElementRequest,
+        CreateDivElementRequest, CreateFormElementRequest, CreateHeadingElementRequest,
+        CreateHrElementRequest, CreateInputElementRequest, CreateLabelElementRequest,
+        CreateLiElementRequest, CreateParagraphElementRequest, CreateStyleElementRequest,
+        CreateTextNodeRequest, CreateUListElementRequest, HeadImplRequest,
+    },
+    dom_token_list::{AddImplRequest, RemoveImplRequest},
+    element::SetIdImplRequest,
+    global::WindowRequest,
+    history::{History, PushStateImplRequest},
+    html_anchor_element::{SetHrefRequest, SetPushStateHandlerRequest},
+    html_element::{ClassListImplRequest, StyleImplRequest},
+    html_form_element::{HtmlFormElement, SetFormSubmitHandlerImplRequest, SubmitFormRequest},
+    html_input_element::{
+        HtmlInputElement, SetAutocompleteRequest, SetInputRequest, SetPlaceholderRequest,
+        SetRequiredRequest, SetTypeRequest, SetValueRequest,
+    },
+    location::HrefRequest,
+    node::{AppendChildImplRequest, RemoveChildImplRequest},
+    text::SetNodeValueRequest,
+    window::{DocumentImplRequest, HistoryImplRequest, PopStateRequest, Window},
+};
+
+use crate::{
+    transport::{memory_pair, Transport, TransportRx, TransportTx},
+    OctantApplication, OctantServer,
+};
+
+#[derive(Debug, Default)]
+pub struct HeadlessNode {
+    /// The element tag, or `None` for a text node.
+    pub tag: Option<String>,
+    pub text: String,
+    pub children: Vec<RawHandle>,
+    pub attributes: BTreeMap<String, String>,
+    pub classes: BTreeSet<String>,
+    pub style: BTreeMap<String, String>,
+    pub listeners: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+enum HeadlessObject {
+    Node(HeadlessNode),
+    ClassList(RawHandle),
+    Style(RawHandle),
+}
+
+/// The client-side state of a session, as built up by the server's RPCs.
+#[derive(Debug, Default)]
+pub struct HeadlessDom {
+    objects: HashMap<RawHandle, HeadlessObject>,
+    window: Option<RawHandle>,
+    document: Option<RawHandle>,
+    body: Option<RawHandle>,
+    history: Option<RawHandle>,
+}
+
+pub struct HeadlessClient {
+    wire: Wire,
+    url: String,
+    tx: TransportTx,
+    rx: TransportRx,
+    /// The number of [UpMessageList]s the session had run when it last ran out of work.
+    idle: watch::Receiver<Option<u64>>,
+    sent: u64,
+    hello: Option<SessionHello>,
+    received: u64,
+    dom: HeadlessDom,
+    pending_futures: Vec<(String, RawHandle)>,
+    mirror: Rc<Runtime>,
+    /// Runs the deletes that [Self::mirror] spawns as [Self::peers] drops its objects.
+    mirror_pool: EventPool,
+    /// Keeps each of the client's objects in [Self::mirror] until the server deletes it.
+    peers: HashMap<RawHandle, Rcf<dyn Peer>>,
+}
+
+/// The tag and handle of the element a message creates.
+fn created_element(message: &dyn DownMessage) -> Option<(String, RawHandle)> {
+    let message: &dyn Any = message;
+    macro_rules! elements {
+        ($($request:ty => $tag:expr),* $(,)?) => {
+            $(
+                if let Some(m) = message.downcast_ref::<$request>() {
+                    return Some(($tag.to_owned(), m.down.raw()));
+                }
+            )*
+        };
+    }
+    elements!(
+        CreateDivElementRequest => "div",
+        CreateAnchorElementRequest => "a",
+        CreateFormElementRequest => "form",
+        CreateHrElementRequest => "hr",
+        CreateBrElementRequest => "br",
+        CreateInputElementRequest => "input",
+        CreateLabelElementRequest => "label",
+        CreateParagraphElementRequest => "p",
+        CreateUListElementRequest => "ul",
+        CreateLiElementRequest => "li",
+        CreateStyleElementRequest => "style",
+    );
+    let m = message.downcast_ref::<CreateHeadingElementRequest>()?;
+    Some((format!("h{}", m.n), m.down.raw()))
+}
+
+/// The attribute a message sets on an input or anchor element, and its value.
+fn set_attribute(message: &dyn DownMessage) -> Option<(RawHandle, &'static str, String)> {
+    let message: &dyn Any = message;
+    if let Some(m) = message.downcast_ref::<SetIdImplRequest>() {
+        Some((m.this.raw_handle(), "id", m.value.clone()))
+    } else if let Some(m) = message.downcast_ref::<SetAutocompleteRequest>() {
+        Some((
+            m.this.raw_handle(),
+            "autocomplete",
+            m.autocomplete.to_string(),
+        ))
+    } else if let Some(m) = message.downcast_ref::<SetTypeRequest>() {
+        Some((m.this.raw_handle(), "type", m.typ.as_string().to_owned()))
+    } else if let Some(m) = message.downcast_ref::<SetValueRequest>() {
+        Some((m.this.raw_handle(), "value", m.value.clone()))
+    } else if let Some(m) = message.downcast_ref::<SetPlaceholderRequest>() {
+        Some((m.this.raw_handle(), "placeholder", m.placeholder.clone()))
+    } else if let Some(m) = message.downcast_ref::<SetRequiredRequest>() {
+        Some((m.this.raw_handle(), "required", m.required.to_string()))
+    } else if let Some(m) = message.downcast_ref::<SetHrefRequest>() {
+        Some((m.this.raw_handle(), "href", m.href.clone()))
+    } else {
+        None
+    }
+}
+
+impl HeadlessDom {
+    pub fn window(&self) -> Option<RawHandle> {
+        self.window
+    }
+    pub fn document(&self) -> Option<RawHandle> {
+        self.document
+    }
+    pub fn body(&self) -> Option<RawHandle> {
+        self.body
+    }
+    pub fn node(&self, handle: RawHandle) -> Option<&HeadlessNode> {
+        match self.objects.get(&handle)? {
+            HeadlessObject::Node(node) => Some(node),
+            _ => None,
+        }
+    }
+    fn node_mut(&mut self, handle: RawHandle) -> OctantResult<&mut HeadlessNode> {
+        let owner = match self.objects.get(&handle) {
+            Some(HeadlessObject::ClassList(owner) | HeadlessObject::Style(owner)) => *owner,
+            _ => handle,
+        };
+        match self.objects.get_mut(&owner) {
+            Some(HeadlessObject::Node(node)) => Ok(node),
+            _ => Err(octant_error!("{:?} is not a node", handle)),
+        }
+    }
+    fn insert_node(&mut self, handle: RawHandle, tag: Option<&str>, text: String) {
+        self.objects.insert(
+            handle,
+            HeadlessObject::Node(HeadlessNode {
+                tag: tag.map(str::to_owned),
+                text,
+                ..HeadlessNode::default()
+            }),
+        );
+    }
+    /// The handles of every element with the given tag below `root`, in document order.
+    pub fn find_all(&self, root: RawHandle, tag: &str) -> Vec<RawHandle> {
+        let mut output = vec![];
+        self.find_all_impl(root, tag, &mut output);
+        output
+    }
+    fn find_all_impl(&self, root: RawHandle, tag: &str, output: &mut Vec<RawHandle>) {
+        if let Some(node) = self.node(root) {
+            if node.tag.as_deref() == Some(tag) {
+                output.push(root);
+            }
+            for child in &node.children {
+                self.find_all_impl(*child, tag, output);
+            }
+        }
+    }
+    pub fn text_content(&self, root: RawHandle) -> String {
+        let mut output = String::new();
+        if let Some(node) = self.node(root) {
+            output.push_str(&node.text);
+            for child in &node.children {
+                output.push_str(&self.text_content(*child));
+            }
+        }
+        output
+    }
+    /// Renders the subtree as HTML, for snapshot assertions.
+    pub fn to_html(&self, root: RawHandle) -> String {
+        let mut output = String::new();
+        self.to_html_impl(root, &mut output).unwrap();
+        output
+    }
+    fn to_html_impl(&self, root: RawHandle, output: &mut String) -> std::fmt::Result {
+        let Some(node) = self.node(root) else {
+            return Ok(());
+        };
+        let Some(tag) = &node.tag else {
+            return write!(output, "{}", node.text);
+        };
+        write!(output, "<{}", tag)?;
+        for (key, value) in &node.attributes {
+            write!(output, " {}={:?}", key, value)?;
+        }
+        if !node.classes.is_empty() {
+            let classes: Vec<_> = node.classes.iter().map(String::as_str).collect();
+            write!(output, " class={:?}", classes.join(" "))?;
+        }
+        write!(output, ">{}", node.text)?;
+        for child in &node.children {
+            self.to_html_impl(*child, output)?;
+        }
+        write!(output, "</{}>", tag)
+    }
+    fn apply(&mut self, message: &dyn DownMessage) -> OctantResult<()> {
+        if let Some((tag, element)) = created_element(message) {
+            self.insert_node(element, Some(&tag), String::new());
+            return Ok(());
+        }
+        if let Some((this, key, value)) = set_attribute(message) {
+            self.node_mut(this)?
+                .attributes
+                .insert(key.to_owned(), value);
+            return Ok(());
+        }
+        let any: &dyn Any = message;
+        if let Some(m) = any.downcast_ref::<WindowRequest>() {
+            self.window = Some(m.down.raw());
+        } else if let Some(m) = any.downcast_ref::<DocumentImplRequest>() {
+            let document = m.down.raw();
+            self.insert_node(document, Some("#document"), String::new());
+            self.document = Some(document);
+        } else if let Some(m) = any.downcast_ref::<HeadImplRequest>() {
+            let head = m.down.raw();
+            self.insert_node(head, Some("head"), String::new());
+            self.node_mut(m.this.raw_handle())?.children.push(head);
+        } else if let Some(m) = any.downcast_ref::<BodyImplRequest>() {
+            let body = m.down.raw();
+            self.insert_node(body, Some("body"), String::new());
+            self.body = Some(body);
+            self.node_mut(m.this.raw_handle())?.children.push(body);
+        } else if let Some(m) = any.downcast_ref::<HistoryImplRequest>() {
+            self.history = Some(m.down.raw());
+        } else if let Some(m) = any.downcast_ref::<CreateTextNodeRequest>() {
+            self.insert_node(m.down.raw(), None, m.text.clone());
+        } else if let Some(m) = any.downcast_ref::<AppendChildImplRequest>() {
+            let child = m.add.raw_handle();
+            let parent = self.node_mut(m.this.raw_handle())?;
+            parent.children.retain(|x| *x != child);
+            parent.children.push(child);
+        } else if let Some(m) = any.downcast_ref::<RemoveChildImplRequest>() {
+            let child = m.add.raw_handle();
+            self.node_mut(m.this.raw_handle())?
+                .children
+                .retain(|x| *x != child);
+        } else if let Some(m) = any.downcast_ref::<SetNodeValueRequest>() {
+            self.node_mut(m.this.raw_handle())?.text = m.value.clone();
+        } else if let Some(m) = any.downcast_ref::<StyleImplRequest>() {
+            self.objects
+                .insert(m.down.raw(), HeadlessObject::Style(m.this.raw_handle()));
+        } else if let Some(m) = any.downcast_ref::<ClassListImplRequest>() {
+            self.objects
+                .insert(m.down.raw(), HeadlessObject::ClassList(m.this.raw_handle()));
+        } else if let Some(m) = any.downcast_ref::<SetPropertyImplRequest>() {
+            self.node_mut(m.this.raw_handle())?
+                .style
+                .insert(m.name.clone(), m.value.clone());
+        } else if let Some(m) = any.downcast_ref::<AddImplRequest>() {
+            self.node_mut(m.this.raw_handle())?
+                .classes
+                .insert(m.token.clone());
+        } else if let Some(m) = any.downcast_ref::<RemoveImplRequest>() {
+            self.node_mut(m.this.raw_handle())?.classes.remove(&m.token);
+        } else if let Some(m) = any.downcast_ref::<SetFormSubmitHandlerImplRequest>() {
+            self.node_mut(m.this.raw_handle())?
+                .listeners
+                .insert("submit".to_owned());
+        } else if let Some(m) = any.downcast_ref::<SetPushStateHandlerRequest>() {
+            self.node_mut(m.this.raw_handle())?
+                .listeners
+                .insert("click".to_owned());
+        } else if !message.deleted_handles().is_empty() {
+            for handle in message.deleted_handles() {
+                self.objects.remove(handle);
+            }
+        } else {
+            tracing::debug!("Headless client ignoring {}", message.message_name());
+        }
+        Ok(())
+    }
+}
+
+impl HeadlessClient {
+    /// Starts a session for `app` on the current [LocalSet](tokio::task::LocalSet), as if a
+    /// browser had loaded `url` and negotiated `wire`.
+    pub fn connect(
+        server: Arc<OctantServer>,
+        app: Arc<dyn OctantApplication>,
+        wire: Wire,
+        url: &str,
+    ) -> OctantResult<Self> {
+        let (client, session) = memory_pair();
+        let (tx, rx) = Box::new(session).split();
+        let (idle_tx, idle) = watch::channel(None);
+        tokio::task::spawn_local(async move {
+            if let Err(e) = server.run_session(app, wire, tx, rx, Some(idle_tx)).await {
+                tracing::error!("Error running headless session: {:?}", e);
+            }
+        });
+        let (tx, rx) = Box::new(client).split();
+        // The mirror's own messages, such as its deletes, have no client to go to.
+        let (mirror_pool, mirror, _) = detached_runtime(wire.proto);
+        Ok(HeadlessClient {
+            wire,
+            url: Url::parse(url)?.to_string(),
+            tx,
+            rx,
+            idle,
+            sent: 0,
+            hello: None,
+            received: 0,
+            dom: HeadlessDom::default(),
+            pending_futures: vec![],
+            mirror,
+            mirror_pool,
+            peers: HashMap::new(),
+        })
+    }
+    pub fn dom(&self) -> &HeadlessDom {
+        &self.dom
+    }
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    /// Futures the server is awaiting, by RPC name, that must be completed with [Self::respond].
+    pub fn pending_futures(&self) -> &[(String, RawHandle)] {
+        &self.pending_futures
+    }
+    /// Processes frames from the server until it has run everything this client sent and has
+    /// nothing left to do. Work that the server awaits from outside its event loop, such as a
+    /// timer, the database or tokio's `yield_now`, is not waited for.
+    pub async fn settle(&mut self) -> OctantResult<()> {
+        loop {
+            let sent = self.sent;
+            self.idle
+                .wait_for(|up_received| *up_received == Some(sent))
+                .await
+                .map_err(|_| octant_error!("session closed"))?;
+            // The session flushes every frame before it reports that it is idle.
+            while let Some(frame) = self.rx.next().now_or_never() {
+                let frame = frame.ok_or_else(|| octant_error!("session closed"))??;
+                self.apply_frame(frame).await?;
+            }
+            if self.sent == sent {
+                return Ok(());
+            }
+        }
+    }
+    async fn apply_frame(&mut self, frame: Frame) -> OctantResult<()> {
+        if self.hello.is_none() {
+            self.hello = Some(self.wire.decode(&frame, OwnedContext::new().borrow())?);
+            return Ok(());
+        }
+        let list: DownMessageList = self.wire.decode(&frame, OwnedContext::new().borrow())?;
+        self.received += 1;
+        let mirror = self.mirror.clone();
+        let mut ctx = OwnedContext::new();
+        ctx.insert_const(&mirror);
+        let mut responses: Vec<Box<dyn UpMessage>> = vec![];
+        for command in list.commands {
+            let message = self
+                .wire
+                .proto
+                .deserialize::<Box<dyn DownMessage>>(&command, ctx.borrow())?;
+            let any: &dyn Any = &*message;
+            let mut peers = vec![];
+            message.mirror(&mirror, &mut peers);
+            let mut futures = vec![];
+            for peer in peers {
+                let handle = peer.raw_handle();
+                if mirror
+                    .lookup(TypedHandle::<dyn AbstractOctantFuture>::new(handle))
+                    .is_ok()
+                {
+                    futures.push(handle);
+                }
+                self.peers.insert(handle, peer);
+            }
+            for handle in message.deleted_handles() {
+                self.peers.remove(handle);
+            }
+            if any.is::<HrefRequest>() {
+                for promise in futures {
+                    responses.push(Box::new(self.future_response(promise, &self.url)?));
+                }
+            } else if let Some(m) = any.downcast_ref::<CancelFutureRequest>() {
+                self.pending_futures.retain(|(_, x)| *x != m.promise);
+            } else {
+                for promise in futures {
+                    self.pending_futures
+                        .push((message.message_name().to_owned(), promise));
+                }
+                self.dom.apply(&*message)?;
+            }
+        }
+        self.mirror_pool.run_until_stalled()?;
+        if !responses.is_empty() {
+            self.send(responses).await?;
+        }
+        Ok(())
+    }
+    fn future_response<T: OctantSerialize>(
+        &self,
+        promise: RawHandle,
+        value: &T,
+    ) -> OctantResult<FutureResponse> {
+        let value = self
+            .wire
+            .proto
+            .serialize(value, OwnedContext::new().borrow())?;
+        Ok(FutureResponse::new(promise, value))
+    }
+    /// Completes a future from [Self::pending_futures] with the client's side of its result.
+    pub async fn respond<T: OctantSerialize>(
+        &mut self,
+        promise: RawHandle,
+        value: &T,
+    ) -> OctantResult<()> {
+        self.pending_futures.retain(|(_, x)| *x != promise);
+        let response = self.future_response(promise, value)?;
+        self.send(vec![Box::new(response)]).await
+    }
+    async fn send(&mut self, messages: Vec<Box<dyn UpMessage>>) -> OctantResult<()> {
+        let commands = messages
+            .iter()
+            .map(|x| Ok(self.wire.proto.serialize(x, OwnedContext::new().borrow())?))
+            .collect::<OctantResult<Vec<_>>>()?;
+        let list = UpMessageList {
+            ack: self.received,
+            commands,
+        };
+        self.tx.send(self.wire.encode(&list)?).await?;
+        self.sent += 1;
+        Ok(())
+    }
+    fn set_input_message(&mut self, input: RawHandle) -> OctantResult<Box<dyn UpMessage>> {
+        let value = self
+            .dom
+            .node_mut(input)?
+            .attributes
+            .get("value")
+            .cloned()
+            .unwrap_or_default();
+        Ok(Box::new(SetInputRequest {
+            this: self
+                .mirror
+                .lookup(TypedHandle::<dyn HtmlInputElement>::new(input))?,
+            value,
+        }))
+    }
+    /// Types into an input element. The server sees the value when the enclosing form is
+    /// submitted, as in a browser.
+    pub fn set_input(&mut self, input: RawHandle, value: &str) -> OctantResult<()> {
+        self.dom
+            .node_mut(input)?
+            .attributes
+            .insert("value".to_owned(), value.to_owned());
+        Ok(())
+    }
+    pub async fn submit_form(&mut self, form: RawHandle) -> OctantResult<()> {
+        let mut messages = vec![];
+        for input in self.dom.find_all(form, "input") {
+            messages.push(self.set_input_message(input)?);
+        }
+        messages.push(Box::new(SubmitFormRequest {
+            this: self
+                .mirror
+                .lookup(TypedHandle::<dyn HtmlFormElement>::new(form))?,
+        }));
+        self.send(messages).await
+    }
+    /// Follows an anchor that was given a push state handler.
+    pub async fn click_anchor(&mut self, anchor: RawHandle) -> OctantResult<()> {
+        let href = self
+            .dom
+            .node_mut(anchor)?
+            .attributes
+            .get("href")
+            .cloned()
+            .ok_or_else(|| octant_error!("anchor has no href"))?;
+        let history = self
+            .dom
+            .history
+            .ok_or_else(|| octant_error!("no history"))?;
+        self.url = Url::parse(&self.url)?.join(&href)?.to_string();
+        let message = PushStateImplRequest {
+            this: self
+                .mirror
+                .lookup(TypedHandle::<dyn History>::new(history))?,
+            url: self.url.clone(),
+        };
+        self.send(vec![Box::new(message)]).await
+    }
+    /// Navigates back or forward to `url`.
+    pub async fn pop_state(&mut self, url: &str) -> OctantResult<()> {
+        let window = self.dom.window.ok_or_else(|| octant_error!("no window"))?;
+        self.url = Url::parse(url)?.to_string();
+        let message = PopStateRequest {
+            this: self.mirror.lookup(TypedHandle::<dyn Window>::new(window))?,
+            url: self.url.clone(),
+        };
+        self.send(vec![Box::new(message)]).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path, path::Path, rc::Rc, sync::Arc};
+
+    use clap::Parser;
+    use marshal_pointer::{Rcf, RcfRef};
+    use tokio::task::LocalSet;
+    use url::Url;
+
+    use octant_components::{
+        css_scope::CssScopeSet,
+        navbar::{style::NavbarStyle, NavbarBuilder},
+        Component, ComponentBuilder,
+    };
+    use octant_error::OctantResult;
+    use octant_runtime_server::proto::{Proto, Wire};
+    use octant_web_sys_server::{global::Global, node::Node, text::RcText};
+
+    use crate::{
+        headless::HeadlessClient, session::Session, OctantApplication, OctantServer,
+        OctantServerOptions,
+    };
+
+    struct TextComponentBuilder {
+        global: Rc<Global>,
+        text: String,
+    }
+
+    struct TextComponent {
+        node: RcText,
+    }
+
+    impl ComponentBuilder for TextComponentBuilder {
+        fn set_self_path(self: &RcfRef<Self>, _: &str) {}
+        fn build_component(self: &RcfRef<Self>) -> OctantResult<Rcf<dyn Component>> {
+            let document = self.global.window().document();
+            Ok(Rcf::new(TextComponent {
+                node: document.create_text_node(self.text.clone()),
+            }))
+        }
+    }
+
+    impl Component for TextComponent {
+        fn node<'a>(self: &'a RcfRef<Self>) -> &'a RcfRef<dyn Node> {
+            &*self.node
+        }
+        fn update_path(self: &RcfRef<Self>, _: &Url) -> OctantResult<()> {
+            Ok(())
+        }
+    }
+
+    struct NavbarApplication;
+
+    impl OctantApplication for NavbarApplication {
+        fn create_component_builder(
+            self: Arc<Self>,
+            session: Rc<Session>,
+        ) -> OctantResult<Rcf<dyn ComponentBuilder>> {
+            let global = session.global();
+            let style = Rc::new(NavbarStyle::new(&mut CssScopeSet::new(global.clone())));
+            let mut navbar = NavbarBuilder::new(global.clone(), style);
+            for (name, url) in [("First", "first"), ("Second", "second")] {
+                let page = Rcf::new(TextComponentBuilder {
+                    global: global.clone(),
+                    text: format!("{} page", url),
+                });
+                navbar.register(name, name, url, page);
+            }
+            Ok(Rcf::new(navbar))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_navbar() -> OctantResult<()> {
+        let db_path = path::absolute(Path::new("../target/test-headless.db"))?;
+        tokio::fs::remove_dir_all(&db_path).await.ok();
+        tokio::fs::create_dir_all(&db_path).await?;
+        let options = OctantServerOptions::parse_from([
+            "octant-server",
+            "--db-path",
+            db_path.to_str().unwrap(),
+            "--bind-http",
+            "127.0.0.1:0",
+        ]);
+        let server = Arc::new(OctantServer::new(options).await?);
+        LocalSet::new()
+            .run_until(async {
+                for wire in [Wire::from(Proto::Json), "fixed+zstd".parse()?] {
+                    let mut client = HeadlessClient::connect(
+                        server.clone(),
+                        Arc::new(NavbarApplication),
+                        wire,
+                        "https://example.com/",
+                    )?;
+                    client.settle().await?;
+                    let dom = client.dom();
+                    let body = dom.body().unwrap();
+                    let anchors = dom.find_all(body, "a");
+                    assert_eq!(anchors.len(), 2);
+                    let second = dom.node(anchors[1]).unwrap();
+                    assert_eq!(second.attributes["href"], "/second");
+                    assert!(second.listeners.contains("click"));
+                    assert_eq!(dom.text_content(body), "FirstSecond");
+                    let items = dom.find_all(body, "li");
+                    assert!(!dom.node(items[1]).unwrap().classes.contains("selected"));
+
+                    client.click_anchor(anchors[1]).await?;
+                    client.settle().await?;
+                    assert_eq!(client.url(), "https://example.com/second");
+                    let dom = client.dom();
+                    assert!(dom.node(items[1]).unwrap().classes.contains("selected"));
+                    assert_eq!(dom.text_content(body), "FirstSecondsecond page");
+                    assert!(client.pending_futures().is_empty());
+                }
+                Ok(())
+            })
+            .await
+    }
+}
@@ -0,0 +1,233 @@
+//! Reports on the state of running sessions, for the admin endpoint.
+
+use std::{collections::HashMap, rc::Rc, sync::Arc, time::Duration};
+
+use futures::future::join_all;
+use marshal::{context::OwnedContext, Serialize};
+use marshal_json::encode::full::JsonEncoderBuilder;
+use parking_lot::Mutex;
+use subtle::ConstantTimeEq;
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    time::timeout,
+};
+use uuid::Uuid;
+use warp::{http::StatusCode, Reply};
+
+use octant_error::OctantResult;
+use octant_executor::{event_loop::EventSpawn, local_set::ThreadStats};
+use octant_runtime_server::runtime::Runtime;
+
+use crate::sink::SinkMetrics;
+
+/// How long to wait for a busy session to answer.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Debug)]
+pub struct PeerCount {
+    pub class: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionReport {
+    pub token: String,
+    pub handles: u64,
+    pub peers: Vec<PeerCount>,
+    pub pending_futures: u64,
+    pub tasks: u64,
+    pub queued_messages: u64,
+    pub queued_bytes: u64,
+    pub peak_queued_messages: u64,
+    pub sent_messages: u64,
+}
+
+/// The load on one of the threads that run sessions.
+#[derive(Serialize, Debug)]
+pub struct ThreadReport {
+    pub active_tasks: u64,
+    pub queued_tasks: u64,
+    pub spawned_tasks: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct SessionsReport {
+    sessions: Vec<SessionReport>,
+    /// Sessions that did not answer in time.
+    unresponsive: Vec<String>,
+    threads: Vec<ThreadReport>,
+}
+
+type ReportRequest = oneshot::Sender<SessionReport>;
+
+/// The running sessions that can be asked for a [SessionReport].
+pub struct IntrospectTable {
+    sessions: Mutex<HashMap<Uuid, UnboundedSender<ReportRequest>>>,
+}
+
+pub struct IntrospectGuard<'a> {
+    token: Uuid,
+    table: &'a IntrospectTable,
+}
+
+impl SessionReport {
+    pub fn new(
+        token: Uuid,
+        runtime: &Runtime,
+        spawn: &EventSpawn,
+        metrics: &SinkMetrics,
+    ) -> Self {
+        let peers = runtime.peer_counts();
+        let metrics = metrics.snapshot();
+        SessionReport {
+            token: token.to_string(),
+            handles: peers.values().sum::<usize>() as u64,
+            peers: peers
+                .into_iter()
+                .map(|(class, count)| PeerCount {
+                    class: class.to_owned(),
+                    count: count as u64,
+                })
+                .collect(),
+            pending_futures: runtime.pending_futures() as u64,
+            tasks: spawn.task_count() as u64,
+            queued_messages: metrics.queued_messages as u64,
+            queued_bytes: metrics.queued_bytes as u64,
+            peak_queued_messages: metrics.peak_queued_messages as u64,
+            sent_messages: metrics.sent_messages,
+        }
+    }
+}
+
+impl From<ThreadStats> for ThreadReport {
+    fn from(stats: ThreadStats) -> Self {
+        ThreadReport {
+            active_tasks: stats.active_tasks as u64,
+            queued_tasks: stats.queued_tasks as u64,
+            spawned_tasks: stats.spawned_tasks,
+        }
+    }
+}
+
+/// Whether an `Authorization` header grants access to the admin endpoint. Nothing is authorized
+/// unless a token is configured, and the token is compared in constant time.
+pub fn is_authorized(admin_token: Option<&str>, authorization: Option<&str>) -> bool {
+    let (Some(admin_token), Some(authorization)) = (admin_token, authorization) else {
+        return false;
+    };
+    let Some(bearer) = authorization.strip_prefix("Bearer ") else {
+        return false;
+    };
+    !admin_token.is_empty() && bool::from(bearer.as_bytes().ct_eq(admin_token.as_bytes()))
+}
+
+impl IntrospectTable {
+    pub fn new() -> Self {
+        IntrospectTable {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+    /// Answers report requests for the session from within its event loop.
+    pub fn register(
+        &self,
+        token: Uuid,
+        runtime: Rc<Runtime>,
+        metrics: Arc<SinkMetrics>,
+    ) -> IntrospectGuard {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sessions.lock().insert(token, tx);
+        runtime
+            .spawner()
+            .clone()
+            .spawn(Self::answer(token, runtime, metrics, rx));
+        IntrospectGuard { token, table: self }
+    }
+    async fn answer(
+        token: Uuid,
+        runtime: Rc<Runtime>,
+        metrics: Arc<SinkMetrics>,
+        mut requests: UnboundedReceiver<ReportRequest>,
+    ) -> OctantResult<()> {
+        while let Some(request) = requests.recv().await {
+            request
+                .send(SessionReport::new(
+                    token,
+                    &runtime,
+                    runtime.spawner(),
+                    &metrics,
+                ))
+                .ok();
+        }
+        Ok(())
+    }
+    async fn report(&self, threads: Vec<ThreadStats>) -> SessionsReport {
+        let requests = self
+            .sessions
+            .lock()
+            .iter()
+            .map(|(token, session)| {
+                let (tx, rx) = oneshot::channel();
+                session.send(tx).ok();
+                (*token, rx)
+            })
+            .collect::<Vec<_>>();
+        let mut report = SessionsReport {
+            sessions: vec![],
+            unresponsive: vec![],
+            threads: threads.into_iter().map(ThreadReport::from).collect(),
+        };
+        let answers = join_all(
+            requests
+                .into_iter()
+                .map(|(token, rx)| async move { (token, timeout(REPORT_TIMEOUT, rx).await) }),
+        )
+        .await;
+        for (token, answer) in answers {
+            match answer {
+                Ok(Ok(session)) => report.sessions.push(session),
+                _ => report.unresponsive.push(token.to_string()),
+            }
+        }
+        report
+    }
+    /// Replies with a JSON report of every session and of the load on each thread.
+    pub async fn reply(&self, threads: Vec<ThreadStats>) -> Box<dyn Reply> {
+        let report = self.report(threads).await;
+        match JsonEncoderBuilder::new().serialize(&report, OwnedContext::new().borrow()) {
+            Ok(json) => Box::new(warp::reply::with_header(
+                json,
+                "Content-Type",
+                "application/json",
+            )),
+            Err(e) => {
+                tracing::error!("Cannot encode session report: {:?}", e);
+                Box::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+impl<'a> Drop for IntrospectGuard<'a> {
+    fn drop(&mut self) {
+        self.table.sessions.lock().remove(&self.token);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::introspect::is_authorized;
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized(Some("secret"), Some("Bearer secret")));
+        assert!(!is_authorized(Some("secret"), Some("Bearer secreT")));
+        assert!(!is_authorized(Some("secret"), Some("Bearer secret2")));
+        assert!(!is_authorized(Some("secret"), Some("secret")));
+        assert!(!is_authorized(Some("secret"), None));
+        assert!(!is_authorized(None, Some("Bearer ")));
+        assert!(!is_authorized(Some(""), Some("Bearer ")));
+    }
+}
@@ -3,54 +3,86 @@
 #![allow(unused_variables)]
 #![feature(trait_upcasting)]
 #![feature(never_type)]
+#![feature(arbitrary_self_types)]
 
 use crate::{
+    introspect::IntrospectTable,
+    limits::{SessionLimiter, SessionLimits},
+    long_poll::LongPollTable,
+    record::Recorder,
+    resume::{reject_resume, Reattach, ReplayControl, ReplaySink, ResumeTable},
     session::{Session, UrlPrefix},
-    sink::BufferedDownMessageSink,
+    sink::{
+        BufferedDownMessageSink, OverflowPolicy, SinkLimits, SinkMetricsSnapshot,
+        SinkMetricsTable,
+    },
+    transport::{Keepalive, Transport, TransportRx, TransportTx, WebSocketTransport},
 };
 use clap::{ Parser};
-use futures::{
-    stream::{SplitSink, SplitStream, StreamExt},
-    SinkExt,
-};
+use futures::{SinkExt, StreamExt};
 use marshal::context::OwnedContext;
-use marshal_fixed::{decode::full::FixedDecoderBuilder, encode::full::FixedEncoderBuilder};
-use marshal_json::{decode::full::JsonDecoderBuilder, encode::full::JsonEncoderBuilder};
+use marshal_fixed::decode::full::FixedDecoderBuilder;
+use marshal_json::decode::full::JsonDecoderBuilder;
 use marshal_pointer::Rcf;
-use octant_components::ComponentBuilder;
+use octant_components::{Component, ComponentBuilder};
 use octant_database::{
     database::{ArcDatabase, Database},
     file::DatabaseFile,
 };
 use octant_error::{octant_error, Context, OctantError, OctantResult};
 use octant_executor::{
-    event_loop::EventPool,
-    local_set::{LocalSetPool, LocalSetSpawn},
+    event_loop::{EventPool, Supervision},
+    local_set::{LocalSetPool, LocalSetSpawn, ThreadStats},
 };
 use octant_runtime_server::{
-    proto::{ Proto, UpMessageList},
+    frame::{Frame, FRAME_HEADER_LEN},
+    heartbeat::heartbeat,
+    proto::{ClientHello, UpMessageList, Wire},
     runtime::Runtime,
+    version::protocol_version,
+    OctantDeserialize,
 };
 use octant_web_sys_server::global::Global;
 use parking_lot::Mutex;
 use std::{
-    future::pending, net::SocketAddr, path::Path, rc::Rc, sync::Arc, thread::available_parallelism,
+    cell::Cell,
+    collections::HashMap,
+    fs::File,
+    future::{pending, poll_fn, Future},
+    net::SocketAddr,
+    path::Path,
+    pin::pin,
+    rc::Rc,
+    sync::Arc,
+    thread::available_parallelism,
     time::Duration,
 };
-use tokio::{sync::mpsc, try_join};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::{interval, timeout},
+    try_join,
+};
+use tracing::Instrument;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+use uuid::Uuid;
 use url::Url;
 use warp::{
-    filters::BoxedFilter,
-    ws::{Message, WebSocket},
-    Filter, Rejection, Reply,
+    filters::BoxedFilter, http::StatusCode, hyper::body::Bytes, Filter, Rejection, Reply,
 };
 
+pub mod headless;
+pub mod introspect;
+pub mod limits;
+pub mod long_poll;
+pub mod record;
+mod resume;
 pub mod session;
-mod sink;
+pub mod sink;
+pub mod transport;
 
 #[derive(Parser, Debug)]
 pub struct OctantServerOptions {
-    #[arg(long, required = true)]
+    #[arg(long, required_unless_present = "replay")]
     pub bind_http: Option<SocketAddr>,
     #[arg(long)]
     pub bind_https: Option<SocketAddr>,
@@ -60,6 +92,67 @@ pub struct OctantServerOptions {
     pub key_path: Option<String>,
     #[arg(long, required = true)]
     pub db_path: String,
+    /// How long a disconnected session waits for the client to reconnect.
+    #[arg(long, default_value_t = 30)]
+    pub resume_grace_secs: u64,
+    /// How many unacknowledged frames a session keeps for a reconnecting client.
+    #[arg(long, default_value_t = 1024)]
+    pub resume_buffer: usize,
+    /// Directory in which to record the traffic of every session, for use with `--replay`.
+    #[arg(long)]
+    pub record_dir: Option<String>,
+    /// Replays a recorded session against the application instead of serving.
+    #[arg(long)]
+    pub replay: Option<String>,
+    /// How many messages a session may queue for a client that is falling behind.
+    #[arg(long, default_value_t = SinkLimits::default().max_queued_messages)]
+    pub max_queued_messages: usize,
+    /// How many bytes of messages a session may queue for a client that is falling behind.
+    #[arg(long, default_value_t = SinkLimits::default().max_queued_bytes)]
+    pub max_queued_bytes: usize,
+    /// The most messages sent to the client in a single frame.
+    #[arg(long, default_value_t = SinkLimits::default().max_batch_messages)]
+    pub max_batch_messages: usize,
+    /// What to do with a session whose queue is full.
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Wait)]
+    pub overflow: OverflowPolicy,
+    /// Enables `/admin/sessions` for requests bearing this token. Without it, every request to the
+    /// endpoint is rejected.
+    #[arg(long)]
+    pub admin_token: Option<String>,
+    /// The largest frame a client may send.
+    #[arg(long, default_value_t = SessionLimits::default().max_frame_bytes)]
+    pub max_frame_bytes: usize,
+    /// The most messages a client may send in a single frame.
+    #[arg(long, default_value_t = SessionLimits::default().max_batch_commands)]
+    pub max_batch_commands: usize,
+    /// The most peers a session may hold at once.
+    #[arg(long, default_value_t = SessionLimits::default().max_handles)]
+    pub max_handles: usize,
+    /// The most messages a client may send per second.
+    #[arg(long, default_value_t = SessionLimits::default().max_messages_per_sec)]
+    pub max_messages_per_sec: u32,
+    /// How often to ping websocket clients, and to send a heartbeat to every client.
+    #[arg(long, default_value_t = 15)]
+    pub ping_interval_secs: u64,
+    /// How long to wait for a message before treating a connection as lost.
+    #[arg(long, default_value_t = 60)]
+    pub idle_timeout_secs: u64,
+    /// The wires the server accepts, most preferred first.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "fixed+zstd,fixed+deflate,fixed,json+zstd,json+deflate,json"
+    )]
+    pub wires: Vec<String>,
+    /// Which spans and events to log, in the syntax of `RUST_LOG`. Each RPC runs in an `rpc` span
+    /// with its `message` type, so `info,[rpc{message=SetInputRequest}]=trace` logs the contents
+    /// of just that message type.
+    #[arg(long, default_value = "info")]
+    pub trace_filter: String,
+    /// Writes spans and events to this file as JSON lines instead of to stderr.
+    #[arg(long)]
+    pub trace_json: Option<String>,
 }
 
 pub trait OctantApplication: Sync + Send {
@@ -71,15 +164,60 @@ pub trait OctantApplication: Sync + Send {
 
 pub struct OctantServer {
     options: OctantServerOptions,
+    wires: Vec<Wire>,
     database: ArcDatabase,
     warp_handlers: Mutex<Vec<WarpHandler>>,
     spawn: Arc<LocalSetSpawn>,
+    resume: ResumeTable,
+    long_poll: Arc<LongPollTable>,
+    sink_metrics: SinkMetricsTable,
+    introspect: IntrospectTable,
 }
 
 impl OctantServerOptions {
     pub fn from_command_line() -> Self {
         Self::parse()
     }
+    pub fn sink_limits(&self) -> SinkLimits {
+        SinkLimits {
+            max_queued_messages: self.max_queued_messages,
+            max_queued_bytes: self.max_queued_bytes,
+            max_batch_messages: self.max_batch_messages,
+            overflow: self.overflow,
+        }
+    }
+    pub fn keepalive(&self) -> Keepalive {
+        Keepalive {
+            ping_interval: Duration::from_secs(self.ping_interval_secs),
+            idle_timeout: Duration::from_secs(self.idle_timeout_secs),
+        }
+    }
+    /// Installs a global [tracing] subscriber as configured by `--trace-filter` and
+    /// `--trace-json`. Records from the `log` crate are forwarded to it.
+    pub fn init_tracing(&self) -> OctantResult<()> {
+        let filter = EnvFilter::try_new(&self.trace_filter).map_err(OctantError::new)?;
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE);
+        let result = if let Some(path) = &self.trace_json {
+            let file = File::create(path).context("Creating trace file")?;
+            builder
+                .json()
+                .with_writer(std::sync::Mutex::new(file))
+                .try_init()
+        } else {
+            builder.try_init()
+        };
+        result.map_err(|e| octant_error!("Cannot install subscriber: {}", e))
+    }
+    pub fn session_limits(&self) -> SessionLimits {
+        SessionLimits {
+            max_frame_bytes: self.max_frame_bytes,
+            max_batch_commands: self.max_batch_commands,
+            max_handles: self.max_handles,
+            max_messages_per_sec: self.max_messages_per_sec,
+        }
+    }
 }
 
 pub type WarpHandler = BoxedFilter<(Box<dyn Reply>,)>;
@@ -100,6 +238,11 @@ where
 
 impl OctantServer {
     pub async fn new(options: OctantServerOptions) -> OctantResult<Self> {
+        let wires = options
+            .wires
+            .iter()
+            .map(|x| x.parse())
+            .collect::<OctantResult<Vec<Wire>>>()?;
         let (spawn, pool) = LocalSetPool::new(available_parallelism().unwrap().get());
         pool.detach();
         let (db_writer, db) = DatabaseFile::<Database>::new(Path::new(&options.db_path))
@@ -108,10 +251,15 @@ impl OctantServer {
         tokio::spawn(db_writer.serialize_every(Duration::from_secs(1)));
         Ok(OctantServer {
             options,
+            wires,
             database: db,
             // handlers: HashMap::new(),
             warp_handlers: Mutex::new(vec![]),
             spawn,
+            resume: ResumeTable::new(),
+            long_poll: LongPollTable::new(),
+            sink_metrics: SinkMetricsTable::new(),
+            introspect: IntrospectTable::new(),
         })
     }
     pub fn database(&self) -> &ArcDatabase {
@@ -120,37 +268,76 @@ impl OctantServer {
     // pub fn add_handler(&mut self, handler: impl Handler) {
     //     self.handlers.insert(handler.prefix(), Arc::new(handler));
     // }
+    /// The send queue metrics of every running session.
+    pub fn sink_metrics(&self) -> Vec<(Uuid, SinkMetricsSnapshot)> {
+        self.sink_metrics.snapshot()
+    }
+    /// The load on each of the threads that run sessions.
+    pub fn thread_stats(&self) -> Vec<ThreadStats> {
+        self.spawn.thread_stats()
+    }
     pub fn add_warp_handler(&mut self, handler: WarpHandler) {
         self.warp_handlers.get_mut().push(handler);
     }
-    fn decode(runtime: &Rc<Runtime>, x: Message) -> OctantResult<Option<UpMessageList>> {
-        if x.is_close() {
-            Ok(None)
-        } else if x.is_text() {
-            let mut ctx = OwnedContext::new();
-            let output =
-                JsonDecoderBuilder::new(x.as_bytes()).deserialize::<UpMessageList>(ctx.borrow())?;
-            Ok(Some(output))
-        } else if x.is_binary() {
-            let mut ctx = OwnedContext::new();
-            let output = FixedDecoderBuilder::new(x.as_bytes())
-                .deserialize::<UpMessageList>(ctx.borrow())?;
-            Ok(Some(output))
+    fn decode<T: OctantDeserialize>(x: Frame) -> OctantResult<T> {
+        let mut ctx = OwnedContext::new();
+        match x {
+            Frame::Text(x) => {
+                Ok(JsonDecoderBuilder::new(x.as_bytes()).deserialize::<T>(ctx.borrow())?)
+            }
+            Frame::Binary(x) => Ok(FixedDecoderBuilder::new(&x).deserialize::<T>(ctx.borrow())?),
+        }
+    }
+    async fn handle_socket(
+        self: Arc<Self>,
+        app: Arc<dyn OctantApplication>,
+        wire: Wire,
+        query: HashMap<String, String>,
+        transport: Box<dyn Transport>,
+    ) -> OctantResult<()> {
+        let (tx, mut rx) = transport.split();
+        let hello = rx
+            .next()
+            .await
+            .ok_or_else(|| octant_error!("Connection closed before hello"))??;
+        self.options.session_limits().check_frame(&hello)?;
+        let hello = Self::decode::<ClientHello>(hello)?;
+        if hello.protocol != protocol_version() {
+            tracing::info!(
+                "Client protocol {:016x} does not match {:016x}, reloading",
+                hello.protocol,
+                protocol_version()
+            );
+            reject_resume(wire, tx).await;
+            return Ok(());
+        }
+        if let Some(token) = query.get("session") {
+            let token: Uuid = token.parse().map_err(OctantError::new)?;
+            let received = query
+                .get("received")
+                .ok_or_else(|| octant_error!("missing received count"))?
+                .parse()
+                .map_err(OctantError::new)?;
+            if let Err(reattach) = self.resume.reattach(token, Reattach { tx, rx, received }) {
+                tracing::info!("Cannot resume unknown session {}", token);
+                reject_resume(wire, reattach.tx).await;
+            }
+            Ok(())
         } else {
-            Ok(None)
+            self.run_socket(app, wire, tx, rx).await
         }
     }
     pub async fn run_socket(
         self: Arc<Self>,
         app: Arc<dyn OctantApplication>,
-        proto: String,
-        tx: SplitSink<WebSocket, Message>,
-        rx: SplitStream<WebSocket>,
+        wire: Wire,
+        tx: TransportTx,
+        rx: TransportRx,
     ) -> OctantResult<()> {
         let spawn = self.spawn.clone();
         spawn
             .spawn_async(move || async move {
-                self.run_socket_local(app, proto, tx, rx).await?;
+                self.run_socket_local(app, wire, tx, rx).await?;
                 Ok(())
             })
             .await?
@@ -158,97 +345,217 @@ impl OctantServer {
     pub async fn run_socket_local(
         self: Arc<Self>,
         app: Arc<dyn OctantApplication>,
-        proto: String,
-        tx: SplitSink<WebSocket, Message>,
-        mut rx: SplitStream<WebSocket>,
+        wire: Wire,
+        tx: TransportTx,
+        rx: TransportRx,
+    ) -> OctantResult<()> {
+        self.run_session(app, wire, tx, rx, None).await
+    }
+    /// Runs a session like [Self::run_socket_local]. Each time the session's event loop waits, for
+    /// a task to be woken or for the socket to take its messages, `idle` is sent the number of
+    /// [UpMessageList]s it has run.
+    pub(crate) async fn run_session(
+        self: Arc<Self>,
+        app: Arc<dyn OctantApplication>,
+        wire: Wire,
+        tx: TransportTx,
+        mut rx: TransportRx,
+        idle: Option<watch::Sender<Option<u64>>>,
     ) -> OctantResult<()> {
-        let proto = proto.parse::<Proto>()?;
+        let proto = wire.proto;
+        let (token, _resume_guard, mut reattach) = self.resume.register();
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        control_tx
+            .send(ReplayControl::Attach {
+                tx,
+                received: 0,
+                up_received: 0,
+            })
+            .ok();
+        let recorder = self
+            .options
+            .record_dir
+            .as_ref()
+            .map(|dir| Recorder::new(Path::new(dir), token, wire))
+            .transpose()?
+            .map(Rc::new);
+        let keepalive = self.options.keepalive();
+        let replay = ReplaySink::new(wire, token, control_rx, self.options.resume_buffer)
+            .with_idle_timeout(keepalive.idle_timeout);
         let (tx_inner, rx_inner) = mpsc::unbounded_channel();
         let mut sink = BufferedDownMessageSink::new(
             proto,
             rx_inner,
-            Box::pin(tx.with(move |list| async move {
-                let mut ctx = OwnedContext::new();
-                match proto {
-                    Proto::Json => Ok(Message::text(
-                        JsonEncoderBuilder::new().serialize(&list, ctx.borrow())?,
-                    )),
-                    Proto::Fixed => Ok(Message::binary(
-                        FixedEncoderBuilder::new().serialize(&list, ctx.borrow())?,
-                    )),
+            Box::pin(replay.with({
+                let recorder = recorder.clone();
+                move |list| {
+                    if let Some(recorder) = &recorder {
+                        recorder.down(&list);
+                    }
+                    async move { wire.encode(&list) }
                 }
             })),
-        );
-        let (spawn, mut pool) = EventPool::new(move |cx| sink.poll_flush(cx));
+        )
+        .with_limits(self.options.sink_limits());
+        let metrics = sink.metrics().clone();
+        let _metrics_guard = self.sink_metrics.register(token, metrics.clone());
+        let (spawn, pool) = EventPool::new(move |cx| sink.poll_flush(cx));
+        let mut pool = pool.with_supervision(Supervision::Log);
+        let up_received = Rc::new(Cell::new(0));
+        let notify_idle = {
+            let up_received = up_received.clone();
+            move || {
+                if let Some(idle) = &idle {
+                    idle.send_replace(Some(up_received.get()));
+                }
+            }
+        };
         let runtime = Rc::new(Runtime::new(proto,tx_inner, spawn.clone()));
         let global = Global::new(runtime);
         let session = Rc::new(Session::new(global.clone()));
-        spawn.spawn({
+        let _introspect_guard = self
+            .introspect
+            .register(token, global.runtime().clone(), metrics);
+        let (expired_tx, expired_rx) = oneshot::channel();
+        let grace = Duration::from_secs(self.options.resume_grace_secs);
+        let mut limiter = SessionLimiter::new(self.options.session_limits());
+        spawn.spawn_supervised(Supervision::Abort, {
             let runtime = global.runtime().clone();
             async move {
-                while let Some(message) = rx.next().await {
-                    let message = message?;
-                    if let Some(message) = Self::decode(&runtime, message)? {
+                loop {
+                    while let Some(message) = rx.next().await {
+                        let message = match message {
+                            Ok(message) => message,
+                            Err(e) => {
+                                tracing::info!("Session {} read failed: {}", token, e);
+                                break;
+                            }
+                        };
+                        let message = match limiter.check_frame(&message).and_then(|()| {
+                            let message = Self::decode::<UpMessageList>(message)?;
+                            limiter.check_batch(&message)?;
+                            Ok(message)
+                        }) {
+                            Ok(message) => message,
+                            Err(e) => {
+                                tracing::warn!("Closing session {}: {}", token, e);
+                                expired_tx.send(()).ok();
+                                return Ok(());
+                            }
+                        };
+                        if let Some(recorder) = &recorder {
+                            recorder.up(&message);
+                        }
+                        up_received.set(up_received.get() + 1);
+                        control_tx.send(ReplayControl::Ack(message.ack)).ok();
                         runtime.run_batch(message)?;
-                    } else {
-                        break;
+                        if let Err(e) = limiter.check_handles(&runtime) {
+                            tracing::warn!("Closing session {}: {}", token, e);
+                            expired_tx.send(()).ok();
+                            return Ok(());
+                        }
+                    }
+                    tracing::info!("Session {} waiting {:?} for reconnect", token, grace);
+                    match timeout(grace, reattach.recv()).await {
+                        Ok(Some(next)) => {
+                            tracing::info!("Session {} resumed at frame {}", token, next.received);
+                            control_tx
+                                .send(ReplayControl::Attach {
+                                    tx: next.tx,
+                                    received: next.received,
+                                    up_received: up_received.get(),
+                                })
+                                .ok();
+                            rx = next.rx;
+                        }
+                        Ok(None) | Err(_) => {
+                            expired_tx.send(()).ok();
+                            return Ok(());
+                        }
                     }
                 }
-                Ok(())
             }
         });
-        spawn.spawn({
-            let global = global.clone();
+        spawn.spawn::<!, _>({
+            let runtime = global.runtime().clone();
             async move {
-                let url = global.window().document().location().href().await?;
-                let url = Url::parse(&url)?;
-                let mut segments = url
-                    .path_segments()
-                    .ok_or_else(|| octant_error!("no url path"))?;
-                let site = segments
-                    .next()
-                    .ok_or_else(|| octant_error!("no site in path"))?;
-                let proto_str = segments
-                    .next()
-                    .ok_or_else(|| octant_error!("no proto in path"))?;
-
-                session.insert_data(UrlPrefix::new(url.join("/")?));
-                log::info!("url = {}", url);
-                let component_builder = app.create_component_builder(session)?;
-                component_builder.set_self_path(&format!("/{}/{}", site, proto_str));
-                let component = component_builder.build_component()?;
-                global
-                    .window()
-                    .document()
-                    .body()
-                    .append_child(component.node().strong());
-                component.update_path(&url)?;
-                global.window().history().set_push_state_handler(Box::new({
-                    let component = Rcf::downgrade(&component);
-                    move |url| {
-                        if let Some(component) = component.upgrade() {
-                            component.update_path(&Url::parse(&url)?)?;
-                        }
-                        Ok(())
-                    }
-                }));
-                global.window().set_pop_state_handler({
-                    let component = Rcf::downgrade(&component);
-                    Box::new(move |url| {
-                        if let Some(component) = component.upgrade() {
-                            component.update_path(&Url::parse(&url)?)?;
-                        }
-                        Ok(())
-                    })
-                });
-                pending::<!>().await;
+                let mut ticks = interval(keepalive.ping_interval);
+                loop {
+                    ticks.tick().await;
+                    heartbeat(&runtime);
+                }
+            }
+        });
+        spawn.spawn_supervised(Supervision::Abort, async move {
+            let _component = Self::start_session(app, global, session).await?;
+            pending::<!>().await;
+            Ok(())
+        });
+        async move {
+            tracing::info!("Running pool");
+            let run = poll_fn(|cx| {
+                let result = pin!(pool.run()).poll(cx);
+                if result.is_pending() {
+                    notify_idle();
+                }
+                result
+            });
+            tokio::select! {
+                result = run => result?,
+                _ = expired_rx => tracing::info!("Session {} expired", token),
+            }
+            tracing::info!("Done running pool");
+            Ok(())
+        }
+        .instrument(tracing::info_span!("session", %token))
+        .await
+    }
+    async fn start_session(
+        app: Arc<dyn OctantApplication>,
+        global: Rc<Global>,
+        session: Rc<Session>,
+    ) -> OctantResult<Rcf<dyn Component>> {
+        let url = global.window().document().location().href().await?;
+        let url = Url::parse(&url)?;
+        Self::start_application(app, session, &url)
+    }
+    /// Renders `app` into the document body of a session whose page is at `url`.
+    pub fn start_application(
+        app: Arc<dyn OctantApplication>,
+        session: Rc<Session>,
+        url: &Url,
+    ) -> OctantResult<Rcf<dyn Component>> {
+        let global = session.global().clone();
+        session.insert_data(UrlPrefix::new(url.join("/")?));
+        tracing::info!("url = {}", url);
+        let component_builder = app.create_component_builder(session)?;
+        component_builder.set_self_path("");
+        let component = component_builder.build_component()?;
+        global
+            .window()
+            .document()
+            .body()
+            .append_child(component.node().strong());
+        component.update_path(url)?;
+        global.window().history().set_push_state_handler(Box::new({
+            let component = Rcf::downgrade(&component);
+            move |url| {
+                if let Some(component) = component.upgrade() {
+                    component.update_path(&Url::parse(&url)?)?;
+                }
                 Ok(())
             }
+        }));
+        global.window().set_pop_state_handler({
+            let component = Rcf::downgrade(&component);
+            Box::new(move |url| {
+                if let Some(component) = component.upgrade() {
+                    component.update_path(&Url::parse(&url)?)?;
+                }
+                Ok(())
+            })
         });
-        log::info!("Running pool");
-        pool.run().await?;
-        log::info!("Done running pool");
-        Ok(())
+        Ok(component)
     }
     pub async fn run(self, app: Arc<dyn OctantApplication>) -> OctantResult<()> {
         Arc::new(self).run_arc(app).await?;
@@ -265,33 +572,152 @@ impl OctantServer {
             .boxed()
     }
     pub async fn run_arc(self: Arc<Self>, app: Arc<dyn OctantApplication>) -> OctantResult<()> {
+        if let Some(path) = self.options.replay.clone() {
+            return self
+                .spawn
+                .spawn_async(move || async move {
+                    Self::replay_recording(app, Path::new(&path)).await
+                })
+                .await?;
+        }
         let statik = Self::statik();
-        let site = warp::path("site")
-            .and(warp::fs::file("./target/www/octant-client/index.html"))
-            .map(Self::add_header);
         let socket = warp::path("socket")
-            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("sec-websocket-protocol"))
             .and(warp::ws())
             .map({
                 let this = self.clone();
                 let app = app.clone();
-                move |proto: String, ws: warp::ws::Ws| {
-                    log::info!("Handling");
+                move |query: HashMap<String, String>,
+                      offered: Option<String>,
+                      ws: warp::ws::Ws|
+                      -> Box<dyn Reply> {
+                    tracing::info!("Handling");
+                    let Some(wire) = Wire::negotiate(offered.as_deref().unwrap_or(""), &this.wires)
+                    else {
+                        tracing::info!("No acceptable wire in {:?}", offered);
+                        return Box::new(StatusCode::BAD_REQUEST);
+                    };
                     let this = this.clone();
                     let app = app.clone();
-                    ws.on_upgrade(|websocket| async move {
-                        log::info!("Upgraded");
-                        let (tx, rx) = websocket.split();
-                        if let Err(e) = this.run_socket(app, proto, tx, rx).await {
-                            log::error!("Error handling websocket: {:?}", e);
+                    let reply = ws
+                        .max_message_size(this.options.max_frame_bytes)
+                        .on_upgrade(move |websocket| async move {
+                            tracing::info!("Upgraded");
+                            let transport = Box::new(
+                                WebSocketTransport::new(websocket)
+                                    .with_keepalive(this.options.keepalive()),
+                            );
+                            if let Err(e) = this.handle_socket(app, wire, query, transport).await {
+                                tracing::error!("Error handling websocket: {:?}", e);
+                            }
+                        });
+                    Box::new(warp::reply::with_header(
+                        reply,
+                        "sec-websocket-protocol",
+                        wire.to_string(),
+                    ))
+                }
+            });
+        let poll_open = warp::post()
+            .and(warp::path("poll"))
+            .and(warp::path("open"))
+            .and(warp::path::end())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("octant-wire"))
+            .map({
+                let this = self.clone();
+                let app = app.clone();
+                move |query: HashMap<String, String>, offered: Option<String>| -> Box<dyn Reply> {
+                    let Some(wire) = Wire::negotiate(offered.as_deref().unwrap_or(""), &this.wires)
+                    else {
+                        tracing::info!("No acceptable wire in {:?}", offered);
+                        return Box::new(StatusCode::BAD_REQUEST);
+                    };
+                    let (id, transport) = this
+                        .long_poll
+                        .open(&this.options.sink_limits(), this.options.keepalive());
+                    let this = this.clone();
+                    let app = app.clone();
+                    tokio::spawn(async move {
+                        let transport = Box::new(transport);
+                        if let Err(e) = this.handle_socket(app, wire, query, transport).await {
+                            tracing::error!("Error handling long poll connection: {:?}", e);
                         }
-                    })
+                    });
+                    Box::new(Self::add_header(warp::reply::with_header(
+                        id.to_string(),
+                        "octant-wire",
+                        wire.to_string(),
+                    )))
+                }
+            });
+        let poll_get = warp::get()
+            .and(warp::path("poll"))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .then({
+                let this = self.clone();
+                move |id: Uuid| {
+                    let this = this.clone();
+                    async move { this.long_poll.get(id).await }
                 }
             });
-        let mut routes: WarpHandler = statik.or(site).or(socket).into_warp_handler();
+        let poll_post = warp::post()
+            .and(warp::path("poll"))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            // A body carries the frames the client queued during its previous post. Bounding it by
+            // the largest frame a session accepts holds long-poll clients to the WebSocket limit.
+            .and(warp::body::content_length_limit(
+                (self.options.max_frame_bytes + FRAME_HEADER_LEN) as u64,
+            ))
+            .and(warp::body::bytes())
+            .then({
+                let this = self.clone();
+                move |id: Uuid, body: Bytes| {
+                    let this = this.clone();
+                    async move { this.long_poll.post(id, body).await }
+                }
+            });
+        let mut routes: WarpHandler = statik
+            .or(socket)
+            .or(poll_open)
+            .or(poll_get)
+            .or(poll_post)
+            .into_warp_handler();
+        let admin = warp::get()
+            .and(warp::path("admin"))
+            .and(warp::path("sessions"))
+            .and(warp::path::end())
+            .and(warp::header::optional::<String>("authorization"))
+            .then({
+                let this = self.clone();
+                move |authorization: Option<String>| {
+                    let this = this.clone();
+                    let authorized = introspect::is_authorized(
+                        this.options.admin_token.as_deref(),
+                        authorization.as_deref(),
+                    );
+                    async move {
+                        if authorized {
+                            this.introspect.reply(this.spawn.thread_stats()).await
+                        } else {
+                            Box::new(StatusCode::UNAUTHORIZED) as Box<dyn Reply>
+                        }
+                    }
+                }
+            });
+        routes = routes.or(admin).into_warp_handler();
         for x in self.warp_handlers.lock().drain(..) {
             routes = routes.or(x).into_warp_handler();
         }
+        // Any other page is an application path served by the client.
+        let site = warp::get()
+            .and(warp::fs::file("./target/www/octant-client/index.html"))
+            .map(Self::add_header);
+        routes = routes.or(site).into_warp_handler();
         let http = async {
             if let Some(bind_http) = self.options.bind_http {
                 warp::serve(routes.clone()).run(bind_http).await;
@@ -0,0 +1,223 @@
+//! Limits on what a client may send to its session, so that a misbehaving client is disconnected
+//! rather than allowed to exhaust the server's memory.
+
+use tokio::time::Instant;
+
+use octant_error::{octant_error, OctantResult};
+use octant_runtime_server::{frame::Frame, proto::UpMessageList, runtime::Runtime};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SessionLimits {
+    /// The largest frame the client may send.
+    pub max_frame_bytes: usize,
+    /// The most messages in a single [UpMessageList].
+    pub max_batch_commands: usize,
+    /// The most peers the session may hold at once.
+    pub max_handles: usize,
+    /// The most messages the client may send per second, averaged over one second.
+    pub max_messages_per_sec: u32,
+}
+
+/// Checks the traffic of one session against its [SessionLimits].
+pub struct SessionLimiter {
+    limits: SessionLimits,
+    /// Messages the client may still send without waiting, refilled at `max_messages_per_sec`.
+    allowance: f64,
+    last_refill: Instant,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        SessionLimits {
+            max_frame_bytes: 16 << 20,
+            max_batch_commands: 1 << 12,
+            max_handles: 1 << 20,
+            max_messages_per_sec: 1000,
+        }
+    }
+}
+
+impl SessionLimits {
+    pub fn check_frame(&self, frame: &Frame) -> OctantResult<()> {
+        let len = frame.as_bytes().len();
+        if len > self.max_frame_bytes {
+            return Err(octant_error!(
+                "frame of {} bytes exceeds the limit of {}",
+                len,
+                self.max_frame_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SessionLimiter {
+    pub fn new(limits: SessionLimits) -> Self {
+        SessionLimiter {
+            limits,
+            allowance: limits.max_messages_per_sec as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    pub fn check_frame(&self, frame: &Frame) -> OctantResult<()> {
+        self.limits.check_frame(frame)
+    }
+    /// Checks a batch before it is run.
+    pub fn check_batch(&mut self, batch: &UpMessageList) -> OctantResult<()> {
+        let limits = &self.limits;
+        if batch.commands.len() > limits.max_batch_commands {
+            return Err(octant_error!(
+                "batch of {} messages exceeds the limit of {}",
+                batch.commands.len(),
+                limits.max_batch_commands
+            ));
+        }
+        let now = Instant::now();
+        let rate = limits.max_messages_per_sec as f64;
+        self.allowance =
+            (self.allowance + now.duration_since(self.last_refill).as_secs_f64() * rate).min(rate);
+        self.last_refill = now;
+        self.allowance -= batch.commands.len() as f64;
+        if self.allowance < 0.0 {
+            return Err(octant_error!(
+                "client exceeded {} messages per second",
+                limits.max_messages_per_sec
+            ));
+        }
+        Ok(())
+    }
+    /// Checks the handles a session holds after running a batch, which may have created any number.
+    pub fn check_handles(&self, runtime: &Runtime) -> OctantResult<()> {
+        let handles = runtime.handle_count();
+        if handles > self.limits.max_handles {
+            return Err(octant_error!(
+                "{} live handles exceeds the limit of {}",
+                handles,
+                self.limits.max_handles
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use marshal::context::OwnedContext;
+
+    use octant_error::OctantResult;
+    use octant_runtime_server::{
+        frame::Frame,
+        immediate_return::ImmediateReturn,
+        octant_future::{FutureResponse, OctantFuture},
+        proto::{Proto, UpMessage, UpMessageList},
+        test_util::detached_runtime,
+    };
+
+    use crate::limits::{SessionLimiter, SessionLimits};
+
+    fn batch(commands: usize) -> UpMessageList {
+        UpMessageList {
+            ack: 0,
+            commands: vec![vec![]; commands],
+        }
+    }
+
+    #[test]
+    fn test_frame_limit() {
+        let limiter = SessionLimiter::new(SessionLimits {
+            max_frame_bytes: 4,
+            ..SessionLimits::default()
+        });
+        assert!(limiter.check_frame(&Frame::Binary(vec![0; 4])).is_ok());
+        assert_eq!(
+            limiter
+                .check_frame(&Frame::Text("hello".to_owned()))
+                .unwrap_err()
+                .to_string(),
+            "frame of 5 bytes exceeds the limit of 4"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_limit() {
+        let mut limiter = SessionLimiter::new(SessionLimits {
+            max_batch_commands: 3,
+            ..SessionLimits::default()
+        });
+        assert!(limiter.check_batch(&batch(3)).is_ok());
+        assert_eq!(
+            limiter.check_batch(&batch(4)).unwrap_err().to_string(),
+            "batch of 4 messages exceeds the limit of 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_limit() {
+        let (_pool, runtime, _) = detached_runtime(Proto::Json);
+        let limiter = SessionLimiter::new(SessionLimits {
+            max_handles: 2,
+            ..SessionLimits::default()
+        });
+        let mut futures = vec![];
+        for _ in 0..2 {
+            futures.push(OctantFuture::<()>::immediate_new(&runtime).0);
+        }
+        assert!(limiter.check_handles(&runtime).is_ok());
+        futures.push(OctantFuture::<()>::immediate_new(&runtime).0);
+        assert_eq!(
+            limiter.check_handles(&runtime).unwrap_err().to_string(),
+            "3 live handles exceeds the limit of 2"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit() {
+        let limits = SessionLimits {
+            max_messages_per_sec: 10,
+            ..SessionLimits::default()
+        };
+        // A full bucket allows a burst of one second's worth of messages.
+        let mut limiter = SessionLimiter::new(limits);
+        assert!(limiter.check_batch(&batch(10)).is_ok());
+        assert_eq!(
+            limiter.check_batch(&batch(1)).unwrap_err().to_string(),
+            "client exceeded 10 messages per second"
+        );
+        // The bucket refills at the limit, and no further than one second's worth.
+        let mut limiter = SessionLimiter::new(limits);
+        assert!(limiter.check_batch(&batch(10)).is_ok());
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(limiter.check_batch(&batch(5)).is_ok());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(limiter.check_batch(&batch(10)).is_ok());
+        assert!(limiter.check_batch(&batch(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replayed_future_response() -> OctantResult<()> {
+        let (_pool, runtime, _) = detached_runtime(Proto::Json);
+        let (future, (promise, ())) = OctantFuture::<()>::immediate_new(&runtime);
+        let value = runtime
+            .proto()
+            .serialize(&(), OwnedContext::new().borrow())?;
+        let response: Box<dyn UpMessage> = Box::new(FutureResponse::new(promise.raw(), value));
+        let command = runtime
+            .proto()
+            .serialize(&response, OwnedContext::new().borrow())?;
+        let replayed = UpMessageList {
+            ack: 0,
+            commands: vec![command.clone(), command],
+        };
+        let mut limiter = SessionLimiter::new(SessionLimits::default());
+        limiter.check_batch(&replayed)?;
+        // The session's reader fails with this error, which closes the session.
+        assert_eq!(
+            runtime.run_batch(replayed).unwrap_err().to_string(),
+            "double return"
+        );
+        future.await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! A [Transport] over plain HTTP requests, for networks that block websockets.
+//!
+//! The client opens a connection with `POST /poll/open`, offering its wires in an `Octant-Wire`
+//! header as it would in `Sec-WebSocket-Protocol`. The response carries the chosen wire in the same
+//! header and the connection id in its body.
+//! Frames travel up in the bodies of `POST /poll/{id}` requests and down in the chunked bodies of
+//! `GET /poll/{id}` requests, each of which stays open for up to the connection's
+//! [Keepalive::ping_interval]. A connection without a request for its [Keepalive::idle_timeout]
+//! is closed. Both directions use the length-prefixed encoding of [Frame::write_to].
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use futures::{channel::mpsc, stream, SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio::time::{sleep, timeout_at, Instant};
+use uuid::Uuid;
+use warp::{
+    http::{Response, StatusCode},
+    hyper::{body::Bytes, Body},
+    Reply,
+};
+
+use octant_error::{octant_error, OctantResult};
+use octant_runtime_server::frame::{Frame, FrameReader};
+
+use crate::{
+    sink::SinkLimits,
+    transport::{Keepalive, Transport, TransportRx, TransportTx},
+};
+
+struct LongPollConnection {
+    up: mpsc::UnboundedSender<OctantResult<Frame>>,
+    down: Arc<tokio::sync::Mutex<mpsc::Receiver<Frame>>>,
+    last_seen: Instant,
+    keepalive: Keepalive,
+}
+
+pub struct LongPollTable {
+    connections: Mutex<HashMap<Uuid, LongPollConnection>>,
+}
+
+/// The server's end of a long polling connection.
+pub struct LongPollTransport {
+    up: mpsc::UnboundedReceiver<OctantResult<Frame>>,
+    down: mpsc::Sender<Frame>,
+}
+
+impl LongPollTable {
+    pub fn new() -> Arc<Self> {
+        Arc::new(LongPollTable {
+            connections: Mutex::new(HashMap::new()),
+        })
+    }
+    /// Opens a connection that holds as many frames for the client between `GET`s as a full send
+    /// queue makes. Beyond that the session's sink sees the client as stalled, and applies its
+    /// [OverflowPolicy](crate::sink::OverflowPolicy).
+    pub fn open(
+        self: &Arc<Self>,
+        limits: &SinkLimits,
+        keepalive: Keepalive,
+    ) -> (Uuid, LongPollTransport) {
+        let id = Uuid::new_v4();
+        let (up_tx, up_rx) = mpsc::unbounded();
+        let (down_tx, down_rx) = mpsc::channel(
+            limits
+                .max_queued_messages
+                .div_ceil(limits.max_batch_messages.max(1)),
+        );
+        self.connections.lock().insert(
+            id,
+            LongPollConnection {
+                up: up_tx,
+                down: Arc::new(tokio::sync::Mutex::new(down_rx)),
+                last_seen: Instant::now(),
+                keepalive,
+            },
+        );
+        tokio::spawn(Self::expire(
+            Arc::downgrade(self),
+            id,
+            keepalive.idle_timeout,
+        ));
+        (
+            id,
+            LongPollTransport {
+                up: up_rx,
+                down: down_tx,
+            },
+        )
+    }
+    async fn expire(this: Weak<Self>, id: Uuid, idle_timeout: Duration) {
+        loop {
+            sleep(idle_timeout).await;
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+            let ref mut connections = *this.connections.lock();
+            let Some(connection) = connections.get(&id) else {
+                return;
+            };
+            if connection.last_seen.elapsed() > idle_timeout || connection.up.is_closed() {
+                tracing::info!("Long poll connection {} expired", id);
+                connections.remove(&id);
+                return;
+            }
+        }
+    }
+    fn touch<T>(&self, id: Uuid, f: impl FnOnce(&LongPollConnection) -> T) -> Option<T> {
+        let ref mut connections = *self.connections.lock();
+        let connection = connections.get_mut(&id)?;
+        if connection.up.is_closed() {
+            connections.remove(&id);
+            return None;
+        }
+        connection.last_seen = Instant::now();
+        Some(f(connection))
+    }
+    /// Handles `POST /poll/{id}`.
+    pub async fn post(&self, id: Uuid, body: Bytes) -> Box<dyn Reply> {
+        let Some(mut up) = self.touch(id, |x| x.up.clone()) else {
+            return Box::new(StatusCode::NOT_FOUND);
+        };
+        let mut reader = FrameReader::new();
+        reader.push(&body);
+        loop {
+            let frame = match reader.next() {
+                Ok(Some(frame)) => Ok(frame),
+                Ok(None) if reader.is_empty() => break,
+                Ok(None) => Err(octant_error!("truncated frame")),
+                Err(e) => Err(e),
+            };
+            let failed = frame.is_err();
+            if up.send(frame).await.is_err() || failed {
+                return Box::new(StatusCode::BAD_REQUEST);
+            }
+        }
+        Box::new(StatusCode::OK)
+    }
+    /// Handles `GET /poll/{id}`.
+    pub async fn get(&self, id: Uuid) -> Box<dyn Reply> {
+        let Some((down, window)) = self.touch(id, |x| (x.down.clone(), x.keepalive.ping_interval))
+        else {
+            return Box::new(StatusCode::NOT_FOUND);
+        };
+        let down = down.lock_owned().await;
+        let deadline = Instant::now() + window;
+        let body = stream::unfold(down, move |mut down| async move {
+            let frame = timeout_at(deadline, down.next()).await.ok()??;
+            let mut chunk = vec![];
+            frame.write_to(&mut chunk);
+            while let Ok(Some(frame)) = down.try_next() {
+                frame.write_to(&mut chunk);
+            }
+            Some((Ok::<_, Infallible>(chunk), down))
+        });
+        Box::new(
+            Response::builder()
+                .header("Content-Type", "application/octet-stream")
+                .header("Cache-Control", "no-cache")
+                .body(Body::wrap_stream(body))
+                .unwrap(),
+        )
+    }
+}
+
+impl Transport for LongPollTransport {
+    fn split(self: Box<Self>) -> (TransportTx, TransportRx) {
+        let tx = self
+            .down
+            .sink_map_err(|e| octant_error!("long poll connection closed: {}", e));
+        (Box::pin(tx), Box::pin(self.up))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use futures::{task::noop_waker_ref, SinkExt};
+    use tokio::{
+        sync::mpsc::unbounded_channel,
+        time::{sleep, Instant},
+    };
+    use uuid::Uuid;
+    use warp::{http::StatusCode, hyper::body::to_bytes, Reply};
+
+    use octant_error::{OctantError, OctantResult};
+    use octant_runtime_server::{
+        heartbeat::heartbeat,
+        proto::{Compression, Proto, Wire},
+        test_util::detached_runtime,
+    };
+
+    use crate::{
+        long_poll::LongPollTable,
+        resume::{ReplayControl, ReplaySink},
+        sink::{BufferedDownMessageSink, OverflowPolicy, SinkLimits},
+        transport::{Keepalive, Transport},
+    };
+
+    const WIRE: Wire = Wire {
+        proto: Proto::Json,
+        compression: Compression::None,
+    };
+
+    fn keepalive() -> Keepalive {
+        Keepalive {
+            ping_interval: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(20),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive() -> OctantResult<()> {
+        let table = LongPollTable::new();
+        let (id, _transport) = table.open(&SinkLimits::default(), keepalive());
+        // A `GET` with nothing to send ends after the ping interval.
+        let start = Instant::now();
+        let response = table.get(id).await.into_response();
+        let body = to_bytes(response.into_body())
+            .await
+            .map_err(OctantError::new)?;
+        assert!(body.is_empty());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+        // The connection is closed once no request has arrived for the idle timeout.
+        sleep(Duration::from_secs(40)).await;
+        let response = table.get(id).await.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stalled_client() {
+        let limits = SinkLimits {
+            max_queued_messages: 4,
+            max_batch_messages: 1,
+            overflow: OverflowPolicy::Disconnect,
+            ..SinkLimits::default()
+        };
+        let table = LongPollTable::new();
+        // A client that never sends a `GET`.
+        let (_id, transport) = table.open(&limits, keepalive());
+        let (tx, _rx) = Box::new(transport).split();
+        let (_pool, runtime, source) = detached_runtime(Proto::Json);
+        let (control_tx, control_rx) = unbounded_channel();
+        control_tx
+            .send(ReplayControl::Attach {
+                tx,
+                received: 0,
+                up_received: 0,
+            })
+            .ok();
+        let replay = ReplaySink::new(WIRE, Uuid::new_v4(), control_rx, 1024);
+        let mut sink = BufferedDownMessageSink::new(
+            Proto::Json,
+            source,
+            Box::pin(replay.with(|list| async move { WIRE.encode(&list) })),
+        )
+        .with_limits(limits);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut result = Poll::Pending;
+        for _ in 0..32 {
+            heartbeat(&runtime);
+            result = sink.poll_flush(&mut cx);
+            if result.is_ready() {
+                break;
+            }
+        }
+        let Poll::Ready(Err(e)) = result else {
+            panic!("expected the session to be disconnected");
+        };
+        assert!(
+            e.to_string()
+                .starts_with("Client fell behind by 4 messages"),
+            "{}",
+            e
+        );
+        let metrics = sink.metrics().snapshot();
+        assert!(metrics.sent_messages < 32 - 4, "{:?}", metrics);
+    }
+}
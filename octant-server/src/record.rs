@@ -0,0 +1,204 @@
+//! Recording of session traffic, and replay of recordings against a fresh [Runtime].
+//!
+//! A recording is a JSON lines file holding a [RecordHeader] followed by one [RecordEntry] per
+//! [UpMessageList] or [DownMessageList], in the order the session handled them.
+
+use std::{
+    cell::RefCell,
+    convert::Infallible,
+    fs::File,
+    future::pending,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::SinkExt;
+use marshal::{context::OwnedContext, Deserialize, Serialize};
+use marshal_json::{
+    decode::full::JsonDecoderBuilder,
+    encode::full::{JsonEncoder, JsonEncoderBuilder},
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::timeout,
+};
+use uuid::Uuid;
+
+use octant_error::{octant_error, OctantError, OctantResult};
+use octant_executor::event_loop::EventPool;
+use octant_runtime_server::{
+    proto::{DownMessageList, UpMessageList, Wire},
+    runtime::Runtime,
+};
+use octant_web_sys_server::global::Global;
+
+use crate::{session::Session, sink::BufferedDownMessageSink, OctantApplication, OctantServer};
+
+/// How long replay waits for the application to send a recorded [DownMessageList].
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordHeader {
+    pub token: String,
+    pub wire: String,
+    /// When the session started, in milliseconds since the unix epoch.
+    pub start_millis: u64,
+}
+
+/// Exactly one of `up` and `down` is set.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordEntry {
+    /// Milliseconds since the session started.
+    pub millis: u64,
+    pub up: Option<UpMessageList>,
+    pub down: Option<DownMessageList>,
+}
+
+pub struct Recorder {
+    start: Instant,
+    file: RefCell<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn new(dir: &Path, token: Uuid, wire: Wire) -> OctantResult<Self> {
+        let file = File::create(dir.join(format!("{}.jsonl", token)))?;
+        let this = Recorder {
+            start: Instant::now(),
+            file: RefCell::new(BufWriter::new(file)),
+        };
+        this.write(&RecordHeader {
+            token: token.to_string(),
+            wire: wire.to_string(),
+            start_millis: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(OctantError::new)?
+                .as_millis() as u64,
+        })?;
+        Ok(this)
+    }
+    fn write<T: Serialize<JsonEncoder>>(&self, value: &T) -> OctantResult<()> {
+        let mut line = JsonEncoderBuilder::new().serialize(value, OwnedContext::new().borrow())?;
+        line.push('\n');
+        let ref mut file = *self.file.borrow_mut();
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+    fn record(&self, up: Option<&UpMessageList>, down: Option<&DownMessageList>) {
+        let entry = RecordEntry {
+            millis: self.start.elapsed().as_millis() as u64,
+            up: up.map(|x| UpMessageList {
+                ack: x.ack,
+                commands: x.commands.clone(),
+            }),
+            down: down.map(|x| DownMessageList {
+                commands: x.commands.clone(),
+            }),
+        };
+        if let Err(e) = self.write(&entry) {
+            tracing::error!("Cannot record session traffic: {:?}", e);
+        }
+    }
+    pub fn up(&self, list: &UpMessageList) {
+        self.record(Some(list), None)
+    }
+    pub fn down(&self, list: &DownMessageList) {
+        self.record(None, Some(list))
+    }
+}
+
+fn read_recording(path: &Path) -> OctantResult<(RecordHeader, Vec<RecordEntry>)> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| octant_error!("empty recording"))??;
+    let header = JsonDecoderBuilder::new(header.as_bytes())
+        .deserialize::<RecordHeader>(OwnedContext::new().borrow())?;
+    let mut entries = vec![];
+    for line in lines {
+        entries.push(
+            JsonDecoderBuilder::new(line?.as_bytes())
+                .deserialize::<RecordEntry>(OwnedContext::new().borrow())?,
+        );
+    }
+    Ok((header, entries))
+}
+
+impl OctantServer {
+    /// Runs `app` in a fresh session, feeding it the [UpMessageList]s of a recording. The
+    /// [DownMessageList]s the application sends are compared against the recording, and replay
+    /// stops at the first error the session raises.
+    pub async fn replay_recording(
+        app: Arc<dyn OctantApplication>,
+        path: &Path,
+    ) -> OctantResult<()> {
+        let (header, entries) = read_recording(path)?;
+        tracing::info!(
+            "Replaying session {} with {} entries",
+            header.token,
+            entries.len()
+        );
+        let wire = header.wire.parse::<Wire>()?;
+        let (down_tx, mut down_rx) = mpsc::unbounded_channel();
+        let (tx_inner, rx_inner) = mpsc::unbounded_channel();
+        let mut sink = BufferedDownMessageSink::new(
+            wire.proto,
+            rx_inner,
+            Box::pin(
+                futures::sink::drain()
+                    .sink_map_err(|e: Infallible| -> OctantError { match e {} })
+                    .with(move |list: DownMessageList| {
+                        let result = down_tx
+                            .send(list)
+                            .map_err(|_| octant_error!("replay finished"));
+                        async move { result }
+                    }),
+            ),
+        );
+        let (spawn, mut pool) = EventPool::new(move |cx| sink.poll_flush(cx));
+        let runtime = Rc::new(Runtime::new(wire.proto, tx_inner, spawn.clone()));
+        let global = Global::new(runtime);
+        let session = Rc::new(Session::new(global.clone()));
+        let (done_tx, done_rx) = oneshot::channel();
+        spawn.spawn({
+            let runtime = global.runtime().clone();
+            async move {
+                for (index, entry) in entries.into_iter().enumerate() {
+                    if let Some(up) = entry.up {
+                        tracing::info!("Replaying entry {} at {}ms", index, entry.millis);
+                        runtime
+                            .run_batch(up)
+                            .map_err(|e| e.context(format!("while replaying entry {}", index)))?;
+                    }
+                    if let Some(expected) = entry.down {
+                        let actual = timeout(REPLAY_TIMEOUT, down_rx.recv())
+                            .await
+                            .ok()
+                            .flatten()
+                            .ok_or_else(|| {
+                                octant_error!("entry {} was never sent during replay", index)
+                            })?;
+                        if actual.commands != expected.commands {
+                            tracing::warn!("Replay diverged from the recording at entry {}", index);
+                        }
+                    }
+                }
+                done_tx.send(()).ok();
+                Ok(())
+            }
+        });
+        spawn.spawn(async move {
+            let _component = Self::start_session(app, global, session).await?;
+            pending::<!>().await;
+            Ok(())
+        });
+        tokio::select! {
+            result = pool.run() => result?,
+            _ = done_rx => tracing::info!("Replay finished"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::SinkExt;
+use parking_lot::Mutex;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use octant_error::{OctantError, OctantResult};
+use octant_runtime_server::{
+    frame::Frame,
+    proto::{SessionHello, Wire},
+};
+
+use crate::transport::{TransportRx, TransportTx};
+
+/// A new connection for an existing session, along with the number of [DownMessageList]s the client
+/// received on previous connections.
+///
+/// [DownMessageList]: octant_runtime_server::proto::DownMessageList
+pub struct Reattach {
+    pub tx: TransportTx,
+    pub rx: TransportRx,
+    pub received: u64,
+}
+
+pub enum ReplayControl {
+    Attach {
+        tx: TransportTx,
+        received: u64,
+        up_received: u64,
+    },
+    Ack(u64),
+}
+
+pub struct ResumeTable {
+    sessions: Mutex<HashMap<Uuid, UnboundedSender<Reattach>>>,
+}
+
+pub struct ResumeGuard<'a> {
+    token: Uuid,
+    table: &'a ResumeTable,
+}
+
+/// Keeps every encoded frame until the client acknowledges it, so that a reconnecting client can
+/// receive the frames that were lost with the previous socket.
+///
+/// While a socket is attached, a frame is only accepted once the previous ones have been written,
+/// so a slow client fills the bounded queue of the [BufferedDownMessageSink] in front of this. At
+/// most `max_frames` frames are kept for replay, not counting those the attached socket has yet to
+/// write. A client that reconnects after missing a dropped frame cannot resume.
+///
+/// [BufferedDownMessageSink]: crate::sink::BufferedDownMessageSink
+pub struct ReplaySink {
+    wire: Wire,
+    token: Uuid,
+    control: UnboundedReceiver<ReplayControl>,
+    socket: Option<TransportTx>,
+    hello: Option<Frame>,
+    frames: VecDeque<Frame>,
+    first: u64,
+    written: u64,
+    max_frames: usize,
+    /// Sent to the client in the [SessionHello].
+    idle_timeout: Option<Duration>,
+}
+
+impl ResumeTable {
+    pub fn new() -> Self {
+        ResumeTable {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn register(&self) -> (Uuid, ResumeGuard, UnboundedReceiver<Reattach>) {
+        let token = Uuid::new_v4();
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sessions.lock().insert(token, tx);
+        (token, ResumeGuard { token, table: self }, rx)
+    }
+    pub fn reattach(&self, token: Uuid, reattach: Reattach) -> Result<(), Reattach> {
+        if let Some(session) = self.sessions.lock().get(&token) {
+            session.send(reattach).map_err(|e| e.0)
+        } else {
+            Err(reattach)
+        }
+    }
+}
+
+impl<'a> Drop for ResumeGuard<'a> {
+    fn drop(&mut self) {
+        self.table.sessions.lock().remove(&self.token);
+    }
+}
+
+impl ReplaySink {
+    pub fn new(
+        wire: Wire,
+        token: Uuid,
+        control: UnboundedReceiver<ReplayControl>,
+        max_frames: usize,
+    ) -> Self {
+        ReplaySink {
+            wire,
+            token,
+            control,
+            socket: None,
+            hello: None,
+            frames: VecDeque::new(),
+            first: 0,
+            written: 0,
+            max_frames,
+            idle_timeout: None,
+        }
+    }
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+    fn end(&self) -> u64 {
+        self.first + self.frames.len() as u64
+    }
+    fn poll_control(&mut self, cx: &mut Context<'_>) -> OctantResult<()> {
+        while let Poll::Ready(Some(control)) = self.control.poll_recv(cx) {
+            match control {
+                ReplayControl::Ack(ack) => {
+                    // The ack is the client's, which cannot have received frames not yet written.
+                    let ack = ack.min(self.written);
+                    while self.first < ack && !self.frames.is_empty() {
+                        self.frames.pop_front();
+                        self.first += 1;
+                    }
+                }
+                ReplayControl::Attach {
+                    tx,
+                    received,
+                    up_received,
+                } => {
+                    if self.first <= received && received <= self.end() {
+                        let idle_timeout_millis = self.idle_timeout.map(|x| x.as_millis() as u64);
+                        self.hello = Some(self.wire.encode(&SessionHello {
+                            token: self.token.to_string(),
+                            received: Some(up_received),
+                            idle_timeout_millis,
+                        })?);
+                        self.socket = Some(tx);
+                        self.written = received;
+                    } else {
+                        tracing::info!(
+                            "Cannot resume session {} from frame {}, buffer starts at {}",
+                            self.token,
+                            received,
+                            self.first
+                        );
+                        self.socket = None;
+                        tokio::task::spawn_local(reject_resume(self.wire, tx));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    fn poll_write(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        let Some(socket) = &mut self.socket else {
+            return Poll::Ready(Ok(()));
+        };
+        loop {
+            if self.hello.is_none() && self.written == self.first + self.frames.len() as u64 {
+                return socket.poll_flush_unpin(cx);
+            }
+            match socket.poll_ready_unpin(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+            if let Some(hello) = self.hello.take() {
+                socket.start_send_unpin(hello)?;
+            } else {
+                let frame = self.frames[(self.written - self.first) as usize].clone();
+                socket.start_send_unpin(frame)?;
+                self.written += 1;
+            }
+        }
+    }
+    fn poll_socket(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        self.poll_control(cx)?;
+        match self.poll_write(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(e)) => {
+                tracing::info!("Session {} disconnected: {}", self.token, e);
+                self.socket = None;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl futures::Sink<Frame> for ReplaySink {
+    type Error = OctantError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        let this = self.get_mut();
+        this.poll_control(cx)?;
+        if this.socket.is_some() && this.written < this.end() {
+            ready!(this.poll_socket(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Frame) -> OctantResult<()> {
+        let this = self.get_mut();
+        this.frames.push_back(item);
+        while this.frames.len() > this.max_frames
+            && (this.socket.is_none() || this.first < this.written)
+        {
+            this.frames.pop_front();
+            this.first += 1;
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        self.get_mut().poll_socket(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Tells a client that its session is gone or that it is out of date, so that it reloads the page
+/// instead of resuming.
+pub async fn reject_resume(wire: Wire, mut tx: TransportTx) {
+    let result: OctantResult<()> = async {
+        let hello = SessionHello {
+            token: String::new(),
+            received: None,
+            idle_timeout_millis: None,
+        };
+        tx.send(wire.encode(&hello)?).await?;
+        tx.close().await?;
+        Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::info!("Cannot reject resume: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::task::{Context, Poll};
+
+    use futures::{channel::mpsc, task::noop_waker_ref, SinkExt, StreamExt};
+    use marshal::context::OwnedContext;
+    use tokio::{
+        sync::mpsc::{unbounded_channel, UnboundedSender},
+        task::LocalSet,
+    };
+    use uuid::Uuid;
+
+    use octant_error::{OctantError, OctantResult};
+    use octant_runtime_server::{
+        frame::Frame,
+        heartbeat::heartbeat,
+        proto::{Compression, Proto, SessionHello, Wire},
+        test_util::detached_runtime,
+    };
+
+    use crate::{
+        resume::{ReplayControl, ReplaySink},
+        sink::{BufferedDownMessageSink, OverflowPolicy, SinkLimits},
+        transport::{memory_pair, Transport, TransportRx, TransportTx},
+    };
+
+    const WIRE: Wire = Wire {
+        proto: Proto::Json,
+        compression: Compression::None,
+    };
+
+    fn replay(max_frames: usize) -> (Uuid, UnboundedSender<ReplayControl>, ReplaySink) {
+        let token = Uuid::new_v4();
+        let (control_tx, control_rx) = unbounded_channel();
+        (
+            token,
+            control_tx,
+            ReplaySink::new(WIRE, token, control_rx, max_frames),
+        )
+    }
+
+    /// The server's end of a new connection, and what the client reads from it.
+    fn socket() -> (TransportTx, TransportRx) {
+        let (server, client) = memory_pair();
+        let (tx, _) = Box::new(server).split();
+        let (_, rx) = Box::new(client).split();
+        (tx, rx)
+    }
+
+    fn attach(
+        control: &UnboundedSender<ReplayControl>,
+        received: u64,
+        up_received: u64,
+    ) -> TransportRx {
+        let (tx, rx) = socket();
+        control
+            .send(ReplayControl::Attach {
+                tx,
+                received,
+                up_received,
+            })
+            .ok();
+        rx
+    }
+
+    fn frame(index: usize) -> Frame {
+        Frame::Text(index.to_string())
+    }
+
+    async fn next(rx: &mut TransportRx) -> OctantResult<Option<Frame>> {
+        rx.next().await.transpose()
+    }
+
+    async fn read_hello(rx: &mut TransportRx) -> OctantResult<SessionHello> {
+        let frame = next(rx).await?.unwrap();
+        WIRE.decode(&frame, OwnedContext::new().borrow())
+    }
+
+    #[tokio::test]
+    async fn test_resume() -> OctantResult<()> {
+        let (token, control, mut replay) = replay(16);
+        let mut rx = attach(&control, 0, 0);
+        for index in 0..3 {
+            replay.send(frame(index)).await?;
+        }
+        let hello = read_hello(&mut rx).await?;
+        assert_eq!(hello.token, token.to_string());
+        assert_eq!(hello.received, Some(0));
+        for index in 0..3 {
+            assert_eq!(next(&mut rx).await?, Some(frame(index)));
+        }
+        control.send(ReplayControl::Ack(1)).ok();
+        // The client reconnects having received two frames, and the server four up messages.
+        let mut rx = attach(&control, 2, 4);
+        replay.flush().await?;
+        let hello = read_hello(&mut rx).await?;
+        assert_eq!(hello.token, token.to_string());
+        assert_eq!(hello.received, Some(4));
+        assert_eq!(next(&mut rx).await?, Some(frame(2)));
+        replay.send(frame(3)).await?;
+        assert_eq!(next(&mut rx).await?, Some(frame(3)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_evicted() -> OctantResult<()> {
+        LocalSet::new()
+            .run_until(async {
+                let (token, control, mut replay) = replay(2);
+                // Frames sent while disconnected are evicted beyond the limit.
+                for index in 0..4 {
+                    replay.send(frame(index)).await?;
+                }
+                let mut rx = attach(&control, 1, 0);
+                replay.flush().await?;
+                let hello = read_hello(&mut rx).await?;
+                assert_eq!(hello.token, "");
+                assert_eq!(hello.received, None);
+                assert_eq!(next(&mut rx).await?, None);
+                let mut rx = attach(&control, 2, 0);
+                replay.flush().await?;
+                assert_eq!(read_hello(&mut rx).await?.token, token.to_string());
+                assert_eq!(next(&mut rx).await?, Some(frame(2)));
+                assert_eq!(next(&mut rx).await?, Some(frame(3)));
+                Ok(())
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_unwritten_kept() -> OctantResult<()> {
+        // Without room to replay anything, frames are still written to a connected client.
+        let (_, control, mut replay) = replay(0);
+        let mut rx = attach(&control, 0, 0);
+        replay.send(frame(0)).await?;
+        replay.send(frame(1)).await?;
+        read_hello(&mut rx).await?;
+        assert_eq!(next(&mut rx).await?, Some(frame(0)));
+        assert_eq!(next(&mut rx).await?, Some(frame(1)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ack_past_written() -> OctantResult<()> {
+        let (_, control, mut replay) = replay(16);
+        let mut rx = attach(&control, 0, 0);
+        replay.feed(frame(0)).await?;
+        control.send(ReplayControl::Ack(1)).ok();
+        replay.flush().await?;
+        read_hello(&mut rx).await?;
+        assert_eq!(next(&mut rx).await?, Some(frame(0)));
+        control.send(ReplayControl::Ack(5)).ok();
+        replay.send(frame(1)).await?;
+        assert_eq!(next(&mut rx).await?, Some(frame(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stalled_receiver() {
+        let (_pool, runtime, source) = detached_runtime(Proto::Json);
+        // A socket that holds one frame and is never read.
+        let (socket, _stalled) = mpsc::channel(0);
+        let (control_tx, control_rx) = unbounded_channel();
+        control_tx
+            .send(ReplayControl::Attach {
+                tx: Box::pin(socket.sink_map_err(OctantError::new)),
+                received: 0,
+                up_received: 0,
+            })
+            .ok();
+        let replay = ReplaySink::new(WIRE, Uuid::new_v4(), control_rx, 1024);
+        let mut sink = BufferedDownMessageSink::new(
+            Proto::Json,
+            source,
+            Box::pin(replay.with(|list| async move { WIRE.encode(&list) })),
+        )
+        .with_limits(SinkLimits {
+            max_queued_messages: 4,
+            max_batch_messages: 1,
+            overflow: OverflowPolicy::Disconnect,
+            ..SinkLimits::default()
+        });
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut result = Poll::Pending;
+        for _ in 0..16 {
+            heartbeat(&runtime);
+            result = sink.poll_flush(&mut cx);
+            if result.is_ready() {
+                break;
+            }
+        }
+        let Poll::Ready(Err(e)) = result else {
+            panic!("expected the session to be disconnected");
+        };
+        assert!(
+            e.to_string()
+                .starts_with("Client fell behind by 4 messages"),
+            "{}",
+            e
+        );
+        let metrics = sink.metrics().snapshot();
+        assert_eq!(metrics.queued_messages, 4);
+        assert_eq!(metrics.peak_queued_messages, 4);
+        assert!(metrics.sent_messages < 16 - 4, "{:?}", metrics);
+    }
+}
@@ -3,10 +3,12 @@ use std::{
     rc::Rc,
 };
 
+use marshal_pointer::Rcf;
 use memo_map::MemoMap;
 use url::Url;
 
-use octant_error::OctantError;
+use octant_error::{OctantError, OctantResult};
+use octant_runtime_server::peer::Peer;
 use octant_web_sys_server::global::Global;
 
 pub struct Session {
@@ -26,6 +28,16 @@ impl Session {
     pub fn global(&self) -> &Rc<Global> {
         &self.global
     }
+    /// Called when an RPC fails on the client, with the peer it was addressed to. Returning an
+    /// error ends the session.
+    pub fn set_rpc_error_handler(
+        &self,
+        handler: impl 'static + Fn(Option<Rcf<dyn Peer>>, OctantError) -> OctantResult<()>,
+    ) {
+        self.global
+            .runtime()
+            .set_rpc_error_handler(Rc::new(handler));
+    }
     pub fn data<T: SessionData + Default>(&self) -> &T {
         self.data
             .get_or_insert(&TypeId::of::<T>(), || Box::<T>::default())
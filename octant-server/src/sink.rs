@@ -1,26 +1,151 @@
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
 };
 
 use futures::{Sink, SinkExt};
+use parking_lot::Mutex;
 use tokio::sync::mpsc::UnboundedReceiver;
+use uuid::Uuid;
 
 use octant_runtime_server::{
-    proto::{DownMessage, DownMessageList, Proto},
+    handle::RawHandle,
+    proto::{CoalesceKey, DownMessage, DownMessageList, Proto},
     reexports::{
         marshal::context::OwnedContext,
-        octant_error::{OctantError, OctantResult},
+        octant_error::{octant_error, OctantError, OctantResult},
     },
 };
 
+/// What a session does when its client falls behind and the queue reaches its [SinkLimits].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum OverflowPolicy {
+    /// Stop running the session's tasks until the client catches up.
+    Wait,
+    /// End the session.
+    Disconnect,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SinkLimits {
+    pub max_queued_messages: usize,
+    pub max_queued_bytes: usize,
+    /// The most messages sent in a single [DownMessageList].
+    pub max_batch_messages: usize,
+    pub overflow: OverflowPolicy,
+}
+
+/// Queue depth and throughput of a [BufferedDownMessageSink], readable from any thread.
+#[derive(Default, Debug)]
+pub struct SinkMetrics {
+    queued_messages: AtomicUsize,
+    queued_bytes: AtomicUsize,
+    peak_queued_messages: AtomicUsize,
+    peak_queued_bytes: AtomicUsize,
+    sent_lists: AtomicU64,
+    sent_messages: AtomicU64,
+    sent_bytes: AtomicU64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SinkMetricsSnapshot {
+    pub queued_messages: usize,
+    pub queued_bytes: usize,
+    pub peak_queued_messages: usize,
+    pub peak_queued_bytes: usize,
+    pub sent_lists: u64,
+    pub sent_messages: u64,
+    pub sent_bytes: u64,
+}
+
+/// The [SinkMetrics] of every running session.
+pub struct SinkMetricsTable {
+    sessions: Mutex<HashMap<Uuid, Arc<SinkMetrics>>>,
+}
+
+pub struct SinkMetricsGuard<'a> {
+    token: Uuid,
+    table: &'a SinkMetricsTable,
+}
+
 pub struct BufferedDownMessageSink {
     proto: Proto,
+    limits: SinkLimits,
     source: UnboundedReceiver<Box<dyn DownMessage>>,
-    buffer: Vec<Box<dyn DownMessage>>,
+    buffer: VecDeque<Vec<u8>>,
+    buffer_bytes: usize,
+    metrics: Arc<SinkMetrics>,
     sink: Pin<Box<dyn Sink<DownMessageList, Error = OctantError>>>,
 }
 
+impl Default for SinkLimits {
+    fn default() -> Self {
+        SinkLimits {
+            max_queued_messages: 1 << 16,
+            max_queued_bytes: 64 << 20,
+            max_batch_messages: 1 << 12,
+            overflow: OverflowPolicy::Wait,
+        }
+    }
+}
+
+impl SinkMetrics {
+    pub fn snapshot(&self) -> SinkMetricsSnapshot {
+        SinkMetricsSnapshot {
+            queued_messages: self.queued_messages.load(Ordering::Relaxed),
+            queued_bytes: self.queued_bytes.load(Ordering::Relaxed),
+            peak_queued_messages: self.peak_queued_messages.load(Ordering::Relaxed),
+            peak_queued_bytes: self.peak_queued_bytes.load(Ordering::Relaxed),
+            sent_lists: self.sent_lists.load(Ordering::Relaxed),
+            sent_messages: self.sent_messages.load(Ordering::Relaxed),
+            sent_bytes: self.sent_bytes.load(Ordering::Relaxed),
+        }
+    }
+    fn set_queued(&self, messages: usize, bytes: usize) {
+        self.queued_messages.store(messages, Ordering::Relaxed);
+        self.queued_bytes.store(bytes, Ordering::Relaxed);
+        self.peak_queued_messages
+            .fetch_max(messages, Ordering::Relaxed);
+        self.peak_queued_bytes.fetch_max(bytes, Ordering::Relaxed);
+    }
+    fn add_sent(&self, messages: usize, bytes: usize) {
+        self.sent_lists.fetch_add(1, Ordering::Relaxed);
+        self.sent_messages
+            .fetch_add(messages as u64, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl SinkMetricsTable {
+    pub fn new() -> Self {
+        SinkMetricsTable {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn register(&self, token: Uuid, metrics: Arc<SinkMetrics>) -> SinkMetricsGuard {
+        self.sessions.lock().insert(token, metrics);
+        SinkMetricsGuard { token, table: self }
+    }
+    pub fn snapshot(&self) -> Vec<(Uuid, SinkMetricsSnapshot)> {
+        self.sessions
+            .lock()
+            .iter()
+            .map(|(token, metrics)| (*token, metrics.snapshot()))
+            .collect()
+    }
+}
+
+impl<'a> Drop for SinkMetricsGuard<'a> {
+    fn drop(&mut self) {
+        self.table.sessions.lock().remove(&self.token);
+    }
+}
+
 impl BufferedDownMessageSink {
     pub fn new(
         proto: Proto,
@@ -29,40 +154,303 @@ impl BufferedDownMessageSink {
     ) -> Self {
         BufferedDownMessageSink {
             proto,
+            limits: SinkLimits::default(),
             source,
-            buffer: vec![],
+            buffer: VecDeque::new(),
+            buffer_bytes: 0,
+            metrics: Arc::new(SinkMetrics::default()),
             sink,
         }
     }
+    pub fn with_limits(mut self, limits: SinkLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    pub fn metrics(&self) -> &Arc<SinkMetrics> {
+        &self.metrics
+    }
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= self.limits.max_queued_messages
+            || self.buffer_bytes >= self.limits.max_queued_bytes
+    }
+    /// Moves messages from the runtime into the buffer, up to the [SinkLimits], then drops those
+    /// made [redundant] by others that arrived with them.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> OctantResult<()> {
+        let start = self.buffer.len();
+        let mut messages = vec![];
+        loop {
+            if self.is_full() {
+                match self.limits.overflow {
+                    OverflowPolicy::Wait => break,
+                    OverflowPolicy::Disconnect => {
+                        self.metrics
+                            .set_queued(self.buffer.len(), self.buffer_bytes);
+                        return Err(octant_error!(
+                            "Client fell behind by {} messages ({} bytes).",
+                            self.buffer.len(),
+                            self.buffer_bytes
+                        ));
+                    }
+                }
+            }
+            let Poll::Ready(Some(message)) = self.source.poll_recv(cx) else {
+                break;
+            };
+            let mut ctx = OwnedContext::new();
+            let serialized = self.proto.serialize(&message, ctx.borrow())?;
+            let _span = tracing::debug_span!(
+                "rpc",
+                direction = "down",
+                message = message.message_name(),
+                handle = ?message.handle(),
+                bytes = serialized.len()
+            )
+            .entered();
+            tracing::trace!(?message);
+            self.buffer_bytes += serialized.len();
+            self.buffer.push_back(serialized);
+            messages.push(message);
+        }
+        let redundant = redundant(&messages);
+        if redundant.contains(&true) {
+            let added: Vec<_> = self.buffer.drain(start..).collect();
+            for (message, redundant) in added.into_iter().zip(redundant) {
+                if redundant {
+                    self.buffer_bytes -= message.len();
+                } else {
+                    self.buffer.push_back(message);
+                }
+            }
+        }
+        self.metrics
+            .set_queued(self.buffer.len(), self.buffer_bytes);
+        Ok(())
+    }
+    /// Writes batches to the sink until it is empty or the sink is not ready. Returns whether
+    /// anything was written.
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> OctantResult<bool> {
+        let mut sent = false;
+        while !self.buffer.is_empty() {
+            if self.sink.poll_ready_unpin(cx)?.is_pending() {
+                break;
+            }
+            let count = self.buffer.len().min(self.limits.max_batch_messages.max(1));
+            let commands: Vec<Vec<u8>> = self.buffer.drain(..count).collect();
+            let bytes = commands.iter().map(|x| x.len()).sum::<usize>();
+            self.buffer_bytes -= bytes;
+            self.metrics.add_sent(count, bytes);
+            tracing::debug!(commands = count, bytes, "sending batch");
+            self.sink.start_send_unpin(DownMessageList { commands })?;
+            sent = true;
+        }
+        self.metrics
+            .set_queued(self.buffer.len(), self.buffer_bytes);
+        Ok(sent)
+    }
+    /// Sends every message produced so far. While the queue is full this stays pending, which
+    /// keeps the [EventPool] from running tasks that would produce more.
+    ///
+    /// [EventPool]: octant_executor::event_loop::EventPool
     pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<OctantResult<()>> {
-        self.source
-            .poll_recv_many(cx, &mut self.buffer, usize::MAX)
-            .is_ready();
-        let mut pending = false;
-        if !self.buffer.is_empty() {
-            if let Poll::Ready(()) = self.sink.poll_ready_unpin(cx)? {
-                let commands = self
-                    .buffer
-                    .drain(..)
-                    .map(|x| {
-                        let mut ctx = OwnedContext::new();
-                        let output = self.proto.serialize(&x, ctx.borrow())?;
-                        Ok(output)
-                    })
-                    .collect::<OctantResult<Vec<_>>>()?;
-                self.sink.start_send_unpin(DownMessageList { commands })?;
-            } else {
-                pending = true;
+        loop {
+            self.poll_fill(cx)?;
+            if !self.poll_send(cx)? {
+                break;
             }
         }
-        if let Poll::Ready(()) = self.sink.poll_flush_unpin(cx)? {
+        let flushed = self.sink.poll_flush_unpin(cx)?.is_ready();
+        if flushed && self.buffer.is_empty() {
+            Poll::Ready(Ok(()))
         } else {
-            pending = true;
-        }
-        if pending {
             Poll::Pending
-        } else {
-            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+/// Finds the messages in a batch that need not be sent: those addressed to a handle that an
+/// earlier message deleted, and setters followed by another with the same [CoalesceKey].
+///
+/// A message addressed by reference keeps its peer alive until it is sent, but one addressed by
+/// [RawHandle], such as a stream credit, can follow the delete of its handle.
+fn redundant(messages: &[Box<dyn DownMessage>]) -> Vec<bool> {
+    let mut redundant = vec![false; messages.len()];
+    let mut deleted = HashSet::<RawHandle>::new();
+    for (message, redundant) in messages.iter().zip(redundant.iter_mut()) {
+        if message.handle().is_some_and(|x| deleted.contains(&x)) {
+            *redundant = true;
+        }
+        deleted.extend(message.deleted_handles().iter().copied());
+    }
+    let mut written = HashSet::<CoalesceKey>::new();
+    for (message, redundant) in messages.iter().zip(redundant.iter_mut()).rev() {
+        if *redundant {
+            continue;
+        }
+        if let Some(key) = message.coalesce_key() {
+            if !written.insert(key) {
+                *redundant = true;
+            }
+        }
+    }
+    redundant
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        mem,
+        rc::Rc,
+        task::{Context, Poll},
+    };
+
+    use futures::{task::noop_waker_ref, SinkExt, StreamExt};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+    use octant_executor::event_loop::EventPool;
+    use octant_runtime_server::{
+        handle::RawHandle,
+        octant_future::cancel_future,
+        proto::{DownMessage, Proto},
+        reexports::octant_error::OctantError,
+        test_util::detached_runtime,
+    };
+    use octant_web_sys_server::global::Global;
+
+    use crate::sink::{redundant, BufferedDownMessageSink};
+
+    /// A [Global] for a [detached_runtime].
+    fn global() -> (
+        EventPool,
+        Rc<Global>,
+        UnboundedReceiver<Box<dyn DownMessage>>,
+    ) {
+        let (pool, runtime, rx) = detached_runtime(Proto::Json);
+        (pool, Global::new(runtime), rx)
+    }
+
+    /// Writes each kind of coalescing property several times.
+    fn write_properties(global: &Global) {
+        let d = global.window().document();
+        let text = d.create_text_node("initial".to_owned());
+        let other = d.create_text_node("other".to_owned());
+        text.set_node_value("first".to_owned());
+        other.set_node_value("other last".to_owned());
+        text.set_node_value("second".to_owned());
+        text.set_node_value("last".to_owned());
+        let div = d.create_div_element();
+        div.style().set_property("color", "red");
+        div.style().set_property("width", "1px");
+        div.style().set_property("color", "blue");
+        div.class_list().add("active");
+        div.class_list().add("hidden");
+        div.class_list().remove("active");
+    }
+
+    fn received(rx: &mut UnboundedReceiver<Box<dyn DownMessage>>) -> Vec<Box<dyn DownMessage>> {
+        let mut messages = vec![];
+        while let Ok(message) = rx.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// The debug output of the messages with the given name that survive coalescing.
+    fn kept(messages: &[Box<dyn DownMessage>], name: &str) -> Vec<String> {
+        messages
+            .iter()
+            .zip(redundant(messages))
+            .filter(|(message, redundant)| !redundant && message.message_name() == name)
+            .map(|(message, _)| format!("{:?}", message))
+            .collect()
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let (_pool, global, mut rx) = global();
+        write_properties(&global);
+        let messages = received(&mut rx);
+        let text = kept(&messages, "SetNodeValueRequest");
+        assert_eq!(text.len(), 2, "{:?}", text);
+        assert!(text[0].contains("\"other last\""), "{:?}", text);
+        assert!(text[1].contains("\"last\""), "{:?}", text);
+        let style = kept(&messages, "SetPropertyImplRequest");
+        assert_eq!(style.len(), 2, "{:?}", style);
+        assert!(style[0].contains("\"1px\""), "{:?}", style);
+        assert!(style[1].contains("\"blue\""), "{:?}", style);
+        let added = kept(&messages, "AddImplRequest");
+        assert_eq!(added.len(), 1, "{:?}", added);
+        assert!(added[0].contains("\"hidden\""), "{:?}", added);
+        let removed = kept(&messages, "RemoveImplRequest");
+        assert_eq!(removed.len(), 1, "{:?}", removed);
+        assert!(removed[0].contains("\"active\""), "{:?}", removed);
+    }
+
+    #[test]
+    fn test_deleted() {
+        let (mut pool, global, mut rx) = global();
+        let runtime = global.runtime();
+        let text = global
+            .window()
+            .document()
+            .create_text_node("text".to_owned());
+        let handle = text.raw_handle();
+        cancel_future(runtime, handle);
+        mem::drop(text);
+        pool.run_until_stalled().unwrap();
+        cancel_future(runtime, handle);
+        cancel_future(runtime, RawHandle::new(handle.index() + 100));
+        let messages = received(&mut rx);
+        assert_eq!(kept(&messages, "DeleteBatchRequest").len(), 1);
+        let cancelled = kept(&messages, "CancelFutureRequest");
+        assert_eq!(cancelled.len(), 2, "{:?}", cancelled);
+        let promise = format!("promise: {:?},", handle);
+        assert!(cancelled[0].contains(&promise), "{:?}", cancelled);
+        assert!(!cancelled[1].contains(&promise), "{:?}", cancelled);
+    }
+
+    #[test]
+    fn test_coalesce_sent() {
+        let (_pool, global, mut rx) = global();
+        write_properties(&global);
+        let messages = received(&mut rx);
+        let expected = redundant(&messages).iter().filter(|x| !**x).count();
+        assert_eq!(messages.len() - expected, 4);
+        let (tx, source) = unbounded_channel();
+        for message in messages {
+            tx.send(message).unwrap();
+        }
+        let (sink, mut lists) = futures::channel::mpsc::unbounded();
+        let mut sink = BufferedDownMessageSink::new(
+            Proto::Json,
+            source,
+            Box::pin(sink.sink_map_err(OctantError::new)),
+        );
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(sink.poll_flush(&mut cx).is_ready());
+        let list = lists.poll_next_unpin(&mut cx);
+        let Poll::Ready(Some(list)) = list else {
+            panic!("expected a batch");
+        };
+        assert_eq!(list.commands.len(), expected);
+        assert_eq!(sink.metrics().snapshot().sent_messages, expected as u64);
+        let json: Vec<_> = list
+            .commands
+            .iter()
+            .map(|x| String::from_utf8(x.clone()).unwrap())
+            .collect();
+        for value in ["first", "second", "red"] {
+            assert!(
+                json.iter().all(|x| !x.contains(&format!("\"{}\"", value))),
+                "{:?}",
+                json
+            );
+        }
+        for value in ["last", "blue", "1px", "hidden"] {
+            assert!(
+                json.iter().any(|x| x.contains(&format!("\"{}\"", value))),
+                "{:?}",
+                json
+            );
         }
     }
 }
@@ -0,0 +1,201 @@
+use std::{future::ready, pin::Pin, time::Duration};
+
+use futures::{channel::mpsc, stream, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+use tokio::time::{interval, timeout};
+use warp::ws::{Message, WebSocket};
+
+use octant_error::{octant_error, OctantError, OctantResult};
+use octant_runtime_server::frame::Frame;
+
+pub type TransportTx = Pin<Box<dyn Send + Sync + Sink<Frame, Error = OctantError>>>;
+pub type TransportRx = Pin<Box<dyn Send + Sync + Stream<Item = OctantResult<Frame>>>>;
+
+/// A framed, bidirectional connection that a session runs over.
+pub trait Transport: 'static + Send {
+    fn split(self: Box<Self>) -> (TransportTx, TransportRx);
+}
+
+pub struct WebSocketTransport {
+    socket: WebSocket,
+    keepalive: Option<Keepalive>,
+}
+
+/// Detects websockets whose peer has gone away without closing the connection.
+#[derive(Copy, Clone, Debug)]
+pub struct Keepalive {
+    /// How often to ping the client.
+    pub ping_interval: Duration,
+    /// How long to wait for any message, including a pong, before failing the connection.
+    pub idle_timeout: Duration,
+}
+
+/// One end of an in-process connection, see [memory_pair].
+pub struct MemoryTransport {
+    tx: mpsc::UnboundedSender<Frame>,
+    rx: mpsc::UnboundedReceiver<Frame>,
+}
+
+impl WebSocketTransport {
+    pub fn new(socket: WebSocket) -> Self {
+        WebSocketTransport {
+            socket,
+            keepalive: None,
+        }
+    }
+    pub fn with_keepalive(mut self, keepalive: Keepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+}
+
+fn frame_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(x) => Message::text(x),
+        Frame::Binary(x) => Message::binary(x),
+    }
+}
+
+/// Writes messages to `socket`, interleaved with a ping every `ping_interval`.
+async fn send_with_pings(
+    mut socket: impl Unpin + Sink<Message, Error = warp::Error>,
+    mut messages: mpsc::Receiver<Message>,
+    ping_interval: Duration,
+) {
+    let mut ping = interval(ping_interval);
+    loop {
+        let message = tokio::select! {
+            message = messages.next() => match message {
+                Some(message) => message,
+                None => break,
+            },
+            _ = ping.tick() => Message::ping(Vec::new()),
+        };
+        if let Err(e) = socket.send(message).await {
+            tracing::info!("Websocket write failed: {}", e);
+            return;
+        }
+    }
+    socket.close().await.ok();
+}
+
+/// Fails once `rx` has produced nothing for `idle_timeout`.
+fn with_idle_timeout<S: Unpin + Stream<Item = OctantResult<Message>>>(
+    rx: S,
+    idle_timeout: Duration,
+) -> impl Stream<Item = OctantResult<Message>> {
+    stream::unfold(Some(rx), move |rx| async move {
+        let mut rx = rx?;
+        match timeout(idle_timeout, rx.next()).await {
+            Ok(Some(message)) => Some((message, Some(rx))),
+            Ok(None) => None,
+            Err(_) => Some((
+                Err(octant_error!(
+                    "no message from client for {:?}",
+                    idle_timeout
+                )),
+                None,
+            )),
+        }
+    })
+}
+
+impl Transport for WebSocketTransport {
+    fn split(self: Box<Self>) -> (TransportTx, TransportRx) {
+        let (tx, rx) = self.socket.split();
+        let rx = rx.map_err(OctantError::from);
+        let (tx, rx): (
+            TransportTx,
+            Pin<Box<dyn Send + Sync + Stream<Item = OctantResult<Message>>>>,
+        ) = match self.keepalive {
+            None => (
+                Box::pin(
+                    tx.sink_map_err(OctantError::from)
+                        .with(|frame| ready(Ok::<_, OctantError>(frame_message(frame)))),
+                ),
+                Box::pin(rx),
+            ),
+            Some(keepalive) => {
+                let (messages_tx, messages_rx) = mpsc::channel(1);
+                tokio::spawn(send_with_pings(tx, messages_rx, keepalive.ping_interval));
+                (
+                    Box::pin(
+                        messages_tx
+                            .sink_map_err(|e| octant_error!("websocket closed: {}", e))
+                            .with(|frame| ready(Ok::<_, OctantError>(frame_message(frame)))),
+                    ),
+                    Box::pin(with_idle_timeout(rx, keepalive.idle_timeout)),
+                )
+            }
+        };
+        let rx = rx
+            .try_take_while(|message| ready(Ok(!message.is_close())))
+            .try_filter_map(|message| {
+                ready(Ok(if message.is_text() {
+                    Some(Frame::Text(message.to_str().unwrap().to_owned()))
+                } else if message.is_binary() {
+                    Some(Frame::Binary(message.into_bytes()))
+                } else {
+                    None
+                }))
+            });
+        (tx, Box::pin(rx))
+    }
+}
+
+/// Creates a connected pair of transports, for running a session without a network.
+pub fn memory_pair() -> (MemoryTransport, MemoryTransport) {
+    let (tx1, rx1) = mpsc::unbounded();
+    let (tx2, rx2) = mpsc::unbounded();
+    (
+        MemoryTransport { tx: tx1, rx: rx2 },
+        MemoryTransport { tx: tx2, rx: rx1 },
+    )
+}
+
+impl Transport for MemoryTransport {
+    fn split(self: Box<Self>) -> (TransportTx, TransportRx) {
+        let tx = self
+            .tx
+            .sink_map_err(|e| octant_error!("memory transport closed: {}", e));
+        (Box::pin(tx), Box::pin(self.rx.map(Ok)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{SinkExt, StreamExt};
+
+    use octant_error::OctantResult;
+    use octant_runtime_server::frame::Frame;
+
+    use crate::transport::{memory_pair, Transport};
+
+    #[tokio::test]
+    async fn test_memory_pair() -> OctantResult<()> {
+        let (a, b) = memory_pair();
+        let (mut a_tx, mut a_rx) = Box::new(a).split();
+        let (mut b_tx, mut b_rx) = Box::new(b).split();
+        a_tx.send(Frame::Text("1".to_owned())).await?;
+        a_tx.send(Frame::Binary(vec![2])).await?;
+        b_tx.send(Frame::Text("3".to_owned())).await?;
+        assert_eq!(b_rx.next().await.unwrap()?, Frame::Text("1".to_owned()));
+        assert_eq!(b_rx.next().await.unwrap()?, Frame::Binary(vec![2]));
+        assert_eq!(a_rx.next().await.unwrap()?, Frame::Text("3".to_owned()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_pair_close() -> OctantResult<()> {
+        let (a, b) = memory_pair();
+        let (mut a_tx, a_rx) = Box::new(a).split();
+        let (mut b_tx, mut b_rx) = Box::new(b).split();
+        a_tx.send(Frame::Text("last".to_owned())).await?;
+        a_tx.close().await?;
+        assert_eq!(b_rx.next().await.unwrap()?, Frame::Text("last".to_owned()));
+        assert!(b_rx.next().await.is_none());
+        // Once one end is dropped, writing to it fails rather than buffering forever.
+        drop(a_rx);
+        assert!(b_tx.send(Frame::Text("lost".to_owned())).await.is_err());
+        Ok(())
+    }
+}
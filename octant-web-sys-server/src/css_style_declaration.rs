@@ -27,8 +27,13 @@ pub trait CssStyleDeclaration: Object {
 
 #[rpc]
 impl dyn CssStyleDeclaration {
-    #[rpc]
-    fn set_property_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>, name: String, value: String) {
+    #[rpc(coalesce(name))]
+    pub fn set_property_impl(
+        self: &RcfRef<Self>,
+        runtime: &Rc<Runtime>,
+        name: String,
+        value: String,
+    ) {
         self.native().set_property(&name, &value)?;
         Ok(())
     }
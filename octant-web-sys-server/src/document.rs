@@ -152,7 +152,7 @@ impl dyn Document {
         self.document().body.get_or_init(|| self.body_impl())
     }
     #[rpc]
-    fn body_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHtmlElement {
+    pub fn body_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHtmlElement {
         Ok(RcHtmlElement::peer_new(self.native().body().unwrap()))
     }
     #[cfg(side = "server")]
@@ -160,7 +160,7 @@ impl dyn Document {
         self.document().head.get_or_init(|| self.head_impl())
     }
     #[rpc]
-    fn head_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHtmlHeadElement {
+    pub fn head_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHtmlHeadElement {
         Ok(RcHtmlHeadElement::peer_new(
             self.native()
                 .head()
@@ -25,12 +25,12 @@ pub trait DomTokenList: Object {
 
 #[rpc]
 impl dyn DomTokenList {
-    #[rpc]
+    #[rpc(coalesce(group = "token", token))]
     pub fn add_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, token: String) {
         self.native.add_1(&token)?;
         Ok(())
     }
-    #[rpc]
+    #[rpc(coalesce(group = "token", token))]
     pub fn remove_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, token: String) {
         self.native.remove_1(&token)?;
         Ok(())
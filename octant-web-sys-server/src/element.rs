@@ -30,7 +30,7 @@ pub trait Element: Node {
 #[rpc]
 impl dyn Element {
     #[rpc]
-    fn set_id_impl(self: &RcfRef<dyn Element>, _: &Rc<Runtime>, value: String) {
+    pub fn set_id_impl(self: &RcfRef<dyn Element>, _: &Rc<Runtime>, value: String) {
         self.native().set_id(&value);
         Ok(())
     }
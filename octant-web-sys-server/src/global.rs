@@ -75,7 +75,7 @@ impl Global {
 }
 
 #[rpc]
-fn window(_: &Rc<Runtime>) -> RcWindow {
+pub fn window(_: &Rc<Runtime>) -> RcWindow {
     Ok(RcWindow::peer_new(web_sys::window().unwrap()))
 }
 
@@ -8,17 +8,10 @@ use crate::{
     object::{Object, ObjectFields},
     octant_runtime::peer::AsNative,
 };
-use marshal::{Deserialize, Serialize};
-use marshal_object::derive_variant;
 use marshal_pointer::RcfRef;
 use octant_error::OctantResult;
 use octant_object::{class, DebugClass};
-use octant_runtime::{
-    proto::{BoxUpMessage, UpMessage},
-    rpc,
-    runtime::Runtime,
-    DeserializePeer, PeerNew, SerializePeer,
-};
+use octant_runtime::{rpc, runtime::Runtime, DeserializePeer, PeerNew, SerializePeer};
 use safe_once::cell::OnceCell;
 use std::{
     cell::{Cell, RefCell},
@@ -55,27 +48,17 @@ pub trait History: Object {
             .location()
             .unwrap()
             .href()?;
-        self.sink().send(Box::new(PushState {
-            history: self.strong(),
-            url,
-        }));
+        (self as &RcfRef<dyn History>).push_state_impl(url);
         Ok(())
     }
 }
 
-#[derive(Serialize, Debug, Deserialize)]
-struct PushState {
-    history: RcHistory,
-    url: String,
-}
-
-derive_variant!(BoxUpMessage, PushState);
-
-impl UpMessage for PushState {
-    #[cfg(side = "server")]
-    fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
-        if let Some(handler) = self.history.push_state_handler.get() {
-            (handler)(self.url)?;
+#[rpc]
+impl dyn History {
+    #[rpc(up)]
+    pub fn push_state_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, url: String) {
+        if let Some(handler) = self.push_state_handler.get() {
+            (handler)(url)?;
         }
         Ok(())
     }
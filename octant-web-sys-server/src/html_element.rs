@@ -37,11 +37,11 @@ pub trait HtmlElement: Element {
 #[rpc]
 impl dyn HtmlElement {
     #[rpc]
-    fn style_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) -> RcCssStyleDeclaration {
+    pub fn style_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) -> RcCssStyleDeclaration {
         Ok(RcCssStyleDeclaration::peer_new(self.native().style()))
     }
     #[rpc]
-    fn class_list_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) -> RcDomTokenList {
+    pub fn class_list_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) -> RcDomTokenList {
         Ok(RcDomTokenList::peer_new(self.native().class_list()))
     }
 }
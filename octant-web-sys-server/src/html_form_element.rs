@@ -2,6 +2,7 @@
 use crate::event_handler::EventHandler;
 #[cfg(side = "client")]
 use crate::event_target::ClientEventHandler;
+use crate::html_input_element::HtmlInputElement;
 use crate::{
     html_element::{HtmlElement, HtmlElementFields},
     html_input_element::RcHtmlInputElement,
@@ -9,17 +10,9 @@ use crate::{
     object::Object,
     octant_runtime::peer::AsNative,
 };
-use marshal::{Deserialize, Serialize};
-use marshal_object::derive_variant;
 use marshal_pointer::{Rcf, RcfRef};
-use octant_error::OctantResult;
 use octant_object::{cast::downcast_object, class, DebugClass};
-use octant_runtime::{
-    proto::{BoxUpMessage, UpMessage},
-    rpc,
-    runtime::Runtime,
-    DeserializePeer, PeerNew, SerializePeer,
-};
+use octant_runtime::{rpc, runtime::Runtime, DeserializePeer, PeerNew, SerializePeer};
 use safe_once::cell::OnceCell;
 use std::{fmt::Debug, rc::Rc};
 #[cfg(side = "client")]
@@ -28,7 +21,6 @@ use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 #[cfg(side = "client")]
 use web_sys::Event;
-use crate::html_input_element::HtmlInputElement;
 
 #[derive(DebugClass, PeerNew, SerializePeer, DeserializePeer)]
 pub struct HtmlFormElementFields {
@@ -51,7 +43,7 @@ pub trait HtmlFormElement: HtmlElement {
 #[rpc]
 impl dyn HtmlFormElement {
     #[rpc]
-    fn set_form_submit_handler_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) {
+    pub fn set_form_submit_handler_impl(self: &RcfRef<Self>, runtime: &Rc<Runtime>) {
         let cb = ClientEventHandler::new({
             let this = Rcf::downgrade(&self.strong());
             move |e: Event| {
@@ -62,8 +54,7 @@ impl dyn HtmlFormElement {
                     {
                         desc.update_input_value();
                     }
-                    this.sink()
-                        .send(Box::new(SubmitForm { form: this.clone() }));
+                    this.submit_form();
                 }
                 Ok(())
             }
@@ -71,19 +62,9 @@ impl dyn HtmlFormElement {
         self.add_listener("submit", cb)?;
         Ok(())
     }
-}
-
-#[derive(Serialize, Debug, Deserialize)]
-struct SubmitForm {
-    form: RcHtmlFormElement,
-}
-
-derive_variant!(BoxUpMessage, SubmitForm);
-
-impl UpMessage for SubmitForm {
-    #[cfg(side = "server")]
-    fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
-        if let Some(handler) = self.form.handler.try_get() {
+    #[rpc(up)]
+    pub fn submit_form(self: &RcfRef<Self>, _: &Rc<Runtime>) {
+        if let Some(handler) = self.handler.try_get() {
             (handler)(())?;
         }
         Ok(())
@@ -4,17 +4,9 @@ use crate::{
     object::Object,
     octant_runtime::peer::AsNative,
 };
-use marshal::{Deserialize, Serialize};
-use marshal_object::derive_variant;
 use marshal_pointer::RcfRef;
-use octant_error::OctantResult;
 use octant_object::{class, DebugClass};
-use octant_runtime::{
-    proto::{BoxUpMessage, UpMessage},
-    rpc,
-    runtime::Runtime,
-    DeserializePeer, PeerNew, SerializePeer,
-};
+use octant_runtime::{rpc, runtime::Runtime, DeserializePeer, PeerNew, SerializePeer};
 use std::{cell::RefCell, rc::Rc};
 use crate::attributes::input_type::InputType;
 
@@ -31,10 +23,7 @@ pub trait HtmlInputElement: HtmlElement {
     #[cfg(side = "client")]
     fn update_input_value(self: &RcfRef<Self>) {
         let this = self as &RcfRef<dyn crate::html_input_element::HtmlInputElement>;
-        this.sink().send(Box::<SetInput>::new(SetInput {
-            element: self.strong(),
-            value: this.native().value(),
-        }));
+        this.set_input(this.native().value());
     }
     #[cfg(side = "server")]
     fn input_value(&self) -> Rc<String> {
@@ -42,21 +31,6 @@ pub trait HtmlInputElement: HtmlElement {
     }
 }
 
-#[derive(Serialize, Debug, Deserialize)]
-struct SetInput {
-    element: RcHtmlInputElement,
-    value: String,
-}
-derive_variant!(BoxUpMessage, SetInput);
-
-impl UpMessage for SetInput {
-    #[cfg(side = "server")]
-    fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
-        *self.element.html_input_element().value.borrow_mut() = Rc::new(self.value);
-        Ok(())
-    }
-}
-
 #[rpc]
 impl dyn HtmlInputElement {
     #[rpc]
@@ -84,4 +58,9 @@ impl dyn HtmlInputElement {
         self.native().set_required(required);
         Ok(())
     }
+    #[rpc(up)]
+    pub fn set_input(self: &RcfRef<Self>, _: &Rc<Runtime>, value: String) {
+        *self.html_input_element().value.borrow_mut() = Rc::new(value);
+        Ok(())
+    }
 }
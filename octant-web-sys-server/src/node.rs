@@ -68,7 +68,7 @@ impl dyn Node {
         }
     }
     #[rpc]
-    fn append_child_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, add: RcNode) -> () {
+    pub fn append_child_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, add: RcNode) -> () {
         self.node()
             .children
             .borrow_mut()
@@ -77,7 +77,7 @@ impl dyn Node {
         Ok(())
     }
     #[rpc]
-    fn remove_child_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, add: RcNode) -> () {
+    pub fn remove_child_impl(self: &RcfRef<Self>, _: &Rc<Runtime>, add: RcNode) -> () {
         self.node()
             .children
             .borrow_mut()
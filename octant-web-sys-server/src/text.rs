@@ -21,7 +21,7 @@ pub trait Text: Node {}
 
 #[rpc]
 impl dyn Text {
-    #[rpc]
+    #[rpc(coalesce)]
     pub fn set_node_value(self: &RcfRef<Self>, _: &Rc<Runtime>, value: String) {
         self.native().set_node_value(Some(&value));
         Ok(())
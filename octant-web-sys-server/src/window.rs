@@ -12,17 +12,11 @@ use crate::{
     request::{RcRequest, Request},
     response::RcResponse,
 };
-use marshal::{Deserialize, Serialize};
-use marshal_object::derive_variant;
 use marshal_pointer::RcfRef;
 use octant_error::{OctantError, OctantResult};
 use octant_object::{class, DebugClass};
 use octant_runtime::{
-    future_return::FutureReturn,
-    octant_future::OctantFuture,
-    proto::{BoxUpMessage, UpMessage},
-    rpc,
-    runtime::Runtime,
+    future_return::FutureReturn, octant_future::OctantFuture, rpc, runtime::Runtime,
     DeserializePeer, PeerNew, SerializePeer,
 };
 use safe_once::cell::OnceCell;
@@ -49,6 +43,19 @@ pub struct WindowFields {
     pop_state_handler: OnceCell<Box<dyn EventHandler<String>>>,
 }
 
+/// Aborts a fetch when the future awaiting it is dropped, as happens when the server cancels it.
+#[cfg(side = "client")]
+struct AbortOnDrop(Option<web_sys::AbortController>);
+
+#[cfg(side = "client")]
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(controller) = self.0.take() {
+            controller.abort();
+        }
+    }
+}
+
 #[cfg(side = "server")]
 pub type FetchFuture<'a> = <() as FetchFutureTrait>::Fut<'a>;
 
@@ -101,7 +108,7 @@ pub trait Window: EventTarget {
 #[rpc]
 impl dyn Window {
     #[rpc]
-    fn document_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcDocument {
+    pub fn document_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcDocument {
         Ok(RcDocument::peer_new(self.native().document().unwrap()))
     }
     #[rpc]
@@ -114,7 +121,7 @@ impl dyn Window {
         Ok(RcNavigator::peer_new(self.native().navigator()))
     }
     #[rpc]
-    fn history_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHistory {
+    pub fn history_impl(self: &RcfRef<Self>, _: &Rc<Runtime>) -> RcHistory {
         Ok(RcHistory::peer_new(self.native().history()?))
     }
     #[rpc]
@@ -123,14 +130,18 @@ impl dyn Window {
         runtime: &Rc<Runtime>,
         req: RcRequest,
     ) -> OctantFuture<Result<RcResponse, OctantError>> {
-        let fetch = self.native().fetch_with_request(req.native());
+        let controller = web_sys::AbortController::new()?;
+        let mut init = web_sys::RequestInit::new();
+        init.signal(Some(&controller.signal()));
+        let fetch = self
+            .native()
+            .fetch_with_request_and_init(req.native(), &init);
         Ok(OctantFuture::spawn(runtime, async move {
+            let mut abort = AbortOnDrop(Some(controller));
+            let response = JsFuture::from(fetch).await.map_err(OctantError::from)?;
+            abort.0 = None;
             Ok(RcResponse::peer_new(
-                JsFuture::from(fetch)
-                    .await
-                    .map_err(OctantError::from)?
-                    .dyn_into()
-                    .map_err(OctantError::from)?,
+                response.dyn_into().map_err(OctantError::from)?,
             ))
         }))
     }
@@ -141,37 +152,24 @@ impl dyn Window {
             "popstate",
             ClientEventHandler::new(move |e| {
                 if let Some(this) = this.upgrade() {
-                    this.sink().send(Box::new(PopState {
-                        window: this.clone(),
-                        url: this
-                            .native()
-                            .document()
-                            .unwrap()
-                            .location()
-                            .unwrap()
-                            .href()
-                            .unwrap(),
-                    }));
+                    let url = this
+                        .native()
+                        .document()
+                        .unwrap()
+                        .location()
+                        .unwrap()
+                        .href()?;
+                    this.pop_state(url);
                 }
                 Ok(())
             }),
         )?;
         Ok(())
     }
-}
-#[derive(Serialize, Debug, Deserialize)]
-struct PopState {
-    window: RcWindow,
-    url: String,
-}
-
-derive_variant!(BoxUpMessage, PopState);
-
-impl UpMessage for PopState {
-    #[cfg(side = "server")]
-    fn run(self: Box<Self>, runtime: &Rc<Runtime>) -> OctantResult<()> {
-        if let Some(handler) = self.window.pop_state_handler.get() {
-            (handler)(self.url)?;
+    #[rpc(up)]
+    pub fn pop_state(self: &RcfRef<Self>, _: &Rc<Runtime>, url: String) {
+        if let Some(handler) = self.pop_state_handler.get() {
+            (handler)(url)?;
         }
         Ok(())
     }